@@ -0,0 +1,11 @@
+fn main() {
+    //sqlx::migrate! embeds migrations/ at compile time, a new one needs a rebuild
+    println!("cargo:rerun-if-changed=migrations");
+    //The embedded copy of pages/ has to be rebuilt when a page changes,
+    //without `embed` pages are read at runtime and editing them needs no rebuild
+    if std::env::var_os("CARGO_FEATURE_EMBED").is_some() {
+        println!("cargo:rerun-if-changed=pages");
+    } else {
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+}
@@ -0,0 +1,22 @@
+-- No IF NOT EXISTS: tables made by hand for the first version have string ids and
+-- plaintext passwords, their users could never log in. Such a database has to fail
+-- here and be moved over by hand, not be taken as migrated.
+CREATE TABLE users (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    first_name VARCHAR(255) NOT NULL,
+    last_name VARCHAR(255) NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    -- PHC formatted Argon2id hash, never the plaintext password
+    password VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE sessions (
+    -- SHA-256 of the session token
+    token_hash BINARY(32) NOT NULL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    -- Milliseconds since the unix epoch
+    created_at BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    INDEX sessions_user_id (user_id),
+    CONSTRAINT sessions_user_fk FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
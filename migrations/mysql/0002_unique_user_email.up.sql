@@ -0,0 +1,6 @@
+-- Emails are stored trimmed and lowercased by the application,
+-- bring rows written before that rule in line. Duplicates left over
+-- after normalisation make this migration fail and must be merged by hand.
+UPDATE users SET email = LOWER(TRIM(email));
+
+CREATE UNIQUE INDEX users_email_unique ON users (email);
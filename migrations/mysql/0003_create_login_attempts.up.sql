@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS login_attempts (
+    -- SHA-256 of the account or client address, see structs::throttle::AttemptKey
+    key_hash BINARY(32) NOT NULL PRIMARY KEY,
+    failures INT NOT NULL,
+    -- Milliseconds since the unix epoch
+    last_failure BIGINT NOT NULL,
+    blocked_until BIGINT NOT NULL
+);
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    -- PHC formatted Argon2id hash, never the plaintext password
+    password TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    -- SHA-256 of the session token
+    token_hash BLOB PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- Milliseconds since the unix epoch
+    created_at INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id ON sessions (user_id);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS login_attempts (
+    -- SHA-256 of the account or client address, see structs::throttle::AttemptKey
+    key_hash BLOB PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    -- Milliseconds since the unix epoch
+    last_failure INTEGER NOT NULL,
+    blocked_until INTEGER NOT NULL
+);
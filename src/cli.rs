@@ -0,0 +1,116 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+
+use crate::{
+    config::{Config, ConfigError, LogFormat, LogLevel, StorageBackend},
+    structs::store::{migrations, sql::open_pool},
+};
+
+#[derive(Parser, Debug)]
+#[command(name = "my_project", about = "Login and profile server")]
+pub struct Cli {
+    /// Config file, defaults to $MY_PROJECT_CONFIG or ./my_project.toml when present
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on, e.g. 0.0.0.0:8080
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<SocketAddr>,
+    /// Where accounts and sessions are kept: memory, sql or sql_cached
+    #[arg(long, value_name = "BACKEND")]
+    pub storage: Option<StorageBackend>,
+    /// Database for the sql storage backends
+    #[arg(long, value_name = "URL")]
+    pub database_url: Option<String>,
+    /// Serve pages from DIR and reload them when they change,
+    /// instead of the copy built into the binary
+    #[arg(long, value_name = "DIR")]
+    pub pages_dir: Option<PathBuf>,
+    /// error, warn, info, debug or trace
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+    /// pretty or json
+    #[arg(long, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// The config file and environment with these flags on top, validated.
+    pub fn load_config(&self) -> Result<Config, ConfigError> {
+        let file = Config::file_path(self.config.clone());
+        let mut config = Config::load(file.as_deref(), std::env::vars())?;
+        self.apply_to(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+    /// Overrides whatever `config` says with the flags that were given.
+    pub fn apply_to(&self, config: &mut Config) {
+        if let Some(listen) = self.listen {
+            config.server.listen = listen;
+        }
+        if let Some(storage) = self.storage {
+            config.storage.backend = storage;
+        }
+        if let Some(url) = &self.database_url {
+            config.storage.url = url.clone();
+        }
+        if let Some(dir) = &self.pages_dir {
+            config.server.pages_dir = Some(dir.clone());
+        }
+        if let Some(level) = self.log_level {
+            config.log.level = level;
+        }
+        if let Some(format) = self.log_format {
+            config.log.format = format;
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the HTTP server (the default when no command is given)
+    Serve,
+    /// Inspect or change the database schema
+    Migrate {
+        /// Database to migrate, defaults to storage.url of the config
+        #[arg(long)]
+        database_url: Option<String>,
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum MigrateAction {
+    /// Apply every pending migration
+    Up,
+    /// Revert the most recently applied migrations
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List applied and pending migrations
+    Status,
+}
+
+/// Runs a `migrate` subcommand against `db_url`, printing what happened.
+pub async fn run_migrate_command(db_url: &str, action: MigrateAction) -> Result<(), String> {
+    let (pool, backend) = open_pool(db_url).await.map_err(|err| err.to_string())?;
+
+    let result = match action {
+        MigrateAction::Up => migrations::migrate_up(&pool, backend)
+            .await
+            .map(|applied| println!("->> Applied migrations {:?}", applied)),
+        MigrateAction::Down { steps } => migrations::migrate_down(&pool, backend, steps)
+            .await
+            .map(|reverted| println!("->> Reverted migrations {:?}", reverted)),
+        MigrateAction::Status => migrations::status(&pool, backend)
+            .await
+            .map(|status| println!("->> Migrations {}", status)),
+    };
+
+    pool.close().await;
+    result.map_err(|err| err.to_string())
+}
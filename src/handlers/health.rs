@@ -0,0 +1,117 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use hyper::{Body, Request, Response, StatusCode, header};
+use serde::Serialize;
+
+use crate::{
+    router::HandlerResult,
+    structs::{AppError, app_state::AppState},
+};
+
+////////////////////////////////////////////////////////////////////
+pub struct HealthConsts {}
+impl HealthConsts {
+    /// A store slower than this counts as down, the probe must not hang with it.
+    pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Degraded,
+    Down,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Component {
+    status: Status,
+    latency_ms: f64,
+    //Only a hint, the cause itself is logged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+/// The `/readyz` answer, `degraded` as soon as one component is down.
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    status: Status,
+    components: BTreeMap<&'static str, Component>,
+}
+
+impl Readiness {
+    pub fn status(&self) -> Status {
+        self.status
+    }
+}
+
+/// Asks every dependency, currently only the store, whether it can answer.
+pub async fn check_readiness(app_state: &AppState) -> Readiness {
+    let mut components = BTreeMap::new();
+    components.insert("store", check_store(app_state).await);
+
+    let status = match components
+        .values()
+        .all(|component| component.status == Status::Ok)
+    {
+        true => Status::Ok,
+        false => Status::Degraded,
+    };
+    Readiness { status, components }
+}
+
+async fn check_store(app_state: &AppState) -> Component {
+    let started = Instant::now();
+    let result = tokio::time::timeout(HealthConsts::CHECK_TIMEOUT, app_state.store().ping()).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let error = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(err)) => {
+            tracing::warn!(error = %err, "readiness: store ping failed");
+            Some("unreachable")
+        }
+        Err(_) => {
+            tracing::warn!(timeout = ?HealthConsts::CHECK_TIMEOUT, "readiness: store ping timed out");
+            Some("timed out")
+        }
+    };
+    Component {
+        status: match error {
+            None => Status::Ok,
+            Some(_) => Status::Down,
+        },
+        latency_ms,
+        error,
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+//Liveness, answering at all is the whole check
+pub async fn handle_get_healthz(_request: Request<Body>, _app_state: AppState) -> HandlerResult {
+    probe_response(StatusCode::OK, r#"{"status":"ok"}"#.to_string())
+}
+
+//Readiness, 503 keeps the load balancer away until the store answers again
+pub async fn handle_get_readyz(_request: Request<Body>, app_state: AppState) -> HandlerResult {
+    let readiness = check_readiness(&app_state).await;
+    let status = match readiness.status() {
+        Status::Ok => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let json =
+        serde_json::to_string(&readiness).map_err(|err| AppError::Internal(err.to_string()))?;
+    probe_response(status, json)
+}
+
+fn probe_response(status: StatusCode, json: String) -> HandlerResult {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(json))
+        .unwrap())
+}
@@ -1,39 +1,36 @@
-use std::convert::Infallible;
-
 use hyper::{Body, Request, Response};
 
 use crate::{
     handlers::sessions::handle_existing_session_in_login,
-    structs::{Routes, app_state::AppState, login::LoginInfo},
+    router::ClientAddr,
+    structs::{
+        AppError, Routes, app_state::AppState, auth::AuthenticatedUser, login::LoginInfo,
+        templates::Flash,
+    },
     utils::{
-        deserialize_json_body, extract_session_id_from_header, response::redirect_with_cookie,
-        response_bad_request,
+        deserialize_json_body, expired_session_cookie, extract_session_id_from_header,
+        redirect::{after_login_target, with_flash},
+        response::redirect_with_cookie,
+        session_cookie,
     },
 };
 
 ///////////////////////////////////////////////////////////////////////////
 
+//Behind RequireAuth
 pub async fn handle_delete_logout(
     request: Request<Body>,
     mut app_state: AppState,
-) -> Result<Response<Body>, Infallible> {
-    println!("->> HANDLER - handle_delete_logout");
-
-    let (parts, _body) = request.into_parts();
-
-    //Checking for already existing session
-    let session_id = match extract_session_id_from_header(&parts.headers) {
-        Ok(id) => id,
-        Err(error) => return Ok(error),
-    };
+) -> Result<Response<Body>, AppError> {
+    let session_id = AuthenticatedUser::of(&request)?.session_token();
 
     //Update App state
-    app_state.delete_session(&session_id).await;
-    app_state.print_sessions().await;
+    app_state.delete_session(session_id).await;
 
     //Transfer to the login page with expired cookie
-    let cookie = format!("session_id=; HttpOnly; Path=/; Max-Age=0");
-    let response = redirect_with_cookie(&cookie, Routes::LOGIN, "Successfully logged out");
+    let cookie = expired_session_cookie(app_state.cookie_config());
+    let target = with_flash(Routes::LOGIN, Flash::LOGGED_OUT);
+    let response = redirect_with_cookie(&cookie, &target, "Successfully logged out");
 
     Ok(response)
 }
@@ -43,44 +40,35 @@ pub async fn handle_delete_logout(
 pub async fn handle_post_login(
     request: Request<Body>,
     app_state: AppState,
-) -> Result<Response<Body>, Infallible> {
-    println!("->> HANDLER - handle_post_login");
-
+) -> Result<Response<Body>, AppError> {
+    let client = ClientAddr::of(&request);
     let (parts, body) = request.into_parts();
 
     //Checking for already existing session
-    let session_id = match extract_session_id_from_header(&parts.headers) {
-        Ok(id) => {
-            println!("->> Session ID found: {}", id);
+    if let Ok(id) = extract_session_id_from_header(&parts.headers, app_state.cookie_config()) {
+        //The token itself is a credential, so it is not logged
+        tracing::debug!("login with a session cookie");
 
-            return handle_existing_session_in_login(&app_state, &id).await;
-        }
-        Err(err) => {
-            //Handle error
-        }
-    };
+        let target = after_login_target(&parts.uri);
+        return Ok(handle_existing_session_in_login(&app_state, &id, &target).await);
+    }
 
     //Extracting loginInfo
-    let login: LoginInfo = match deserialize_json_body(body).await {
-        Ok(u) => u,
-        Err(err) => return Ok(err),
-    };
+    let login: LoginInfo = deserialize_json_body(body).await?;
 
-    //Check for valid user
-    let user_id = match app_state.find_user(login).await {
-        Ok(id) => id,
-        Err(err_msg) => return Ok(response_bad_request(&err_msg)),
-    };
+    //Check for valid user, within the limits on failed logins
+    let user_id = app_state.attempt_login(login, client).await?;
     //Create session
-    let session = match app_state.add_session(user_id).await {
-        Ok(session) => session,
-        Err(err_msg) => return Ok(response_bad_request(&err_msg)),
-    };
-    app_state.print_sessions().await;
-
-    //Create response with the cookie and the redirecting to the home page
-    let cookie = format!("session_id={}; HttpOnly; Path=/", session.session_id());
-    let response = redirect_with_cookie(&cookie, Routes::HOME, "Successfully logged in");
+    let session_token = app_state.add_session(user_id).await?;
+
+    //Create response with the cookie and the redirecting to the page asked for before login
+    let cookie = session_cookie(
+        &session_token,
+        app_state.session_config(),
+        app_state.cookie_config(),
+    );
+    let target = after_login_target(&parts.uri);
+    let response = redirect_with_cookie(&cookie, &target, "Successfully logged in");
 
     Ok(response)
 }
@@ -0,0 +1,20 @@
+use hyper::{Body, Request};
+
+use crate::{
+    metrics::MetricsConsts,
+    router::HandlerResult,
+    structs::{AppError, app_state::AppState},
+    utils::response::response_ok_with_content,
+};
+
+//Scraped by Prometheus, a 404 while `[metrics] enabled` is off
+pub async fn handle_get_metrics(_request: Request<Body>, app_state: AppState) -> HandlerResult {
+    let Some(metrics) = app_state.metrics() else {
+        return Err(AppError::NotFound("Metrics are disabled".to_string()));
+    };
+    let text = metrics.render(app_state.store().as_ref()).await;
+    Ok(response_ok_with_content(
+        text.into_bytes(),
+        MetricsConsts::CONTENT_TYPE,
+    ))
+}
@@ -1,5 +1,72 @@
+pub mod health;
 pub mod login_out;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod page;
 pub mod profile;
 pub mod register;
-pub mod sessions;
\ No newline at end of file
+pub mod sessions;
+
+use crate::{
+    middleware::{CsrfProtection, PageAccess, RequestLog, RequireAuth, SetHeaders, Timing},
+    router::Router,
+    structs::{Pages, Routes},
+    utils::load_user_data,
+};
+
+/// Every route the server answers.
+pub fn app_router() -> Router {
+    //Open to anyone
+    let public = Router::new()
+        .post(Routes::LOGIN, login_out::handle_post_login)
+        .post(Routes::REGISTER, register::handle_post_register)
+        .get(Routes::STATIC, page::handle_get_static)
+        .get(Routes::HEALTH, health::handle_get_healthz)
+        .get(Routes::READY, health::handle_get_readyz);
+    #[cfg(feature = "metrics")]
+    let public = public.get(Routes::METRICS, metrics::handle_get_metrics);
+
+    //Logged in users are sent on to /home
+    let guest_pages = Router::new()
+        .get(Routes::ROOT, |_, _| page::handle_get_root())
+        .get(Routes::LOGIN, |request, state| {
+            page::handle_get_page(request, state, Pages::LOGIN)
+        })
+        .get(Routes::REGISTER, |request, state| {
+            page::handle_get_page(request, state, Pages::REGISTER)
+        })
+        .layer(PageAccess::Guest);
+
+    //Anonymous visitors are sent to /login?next=...
+    let member_pages = Router::new()
+        .get(Routes::HOME, |request, state| {
+            page::handle_get_page(request, state, Pages::HOME)
+        })
+        .get(Routes::PROFILE, |request, state| {
+            page::handle_get_page(request, state, Pages::PROFILE)
+        })
+        .layer(PageAccess::LoggedIn);
+
+    //Needs a live session, handlers read the user with AuthenticatedUser::of
+    let session = Router::new()
+        .delete(Routes::LOGOUT, login_out::handle_delete_logout)
+        .put(Routes::PROFILE, profile::handle_put_profile)
+        .get(Routes::USER_PROFILE, load_user_data)
+        .layer(RequireAuth);
+
+    //Pages first so `Allow` lists GET before POST
+    let router = Router::new()
+        .merge(guest_pages)
+        .merge(member_pages)
+        .merge(public)
+        .merge(session)
+        //Every route, before the login checks; 404 and 405 are answered without it
+        .layer(CsrfProtection);
+    #[cfg(feature = "metrics")]
+    let router = router.around(crate::metrics::RecordMetrics);
+
+    router
+        .around(SetHeaders::security())
+        .around(Timing)
+        .around(RequestLog)
+}
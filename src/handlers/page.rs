@@ -1,15 +1,18 @@
-use std::{convert::Infallible, fs::read_to_string};
-
-use hyper::{Body, Response, StatusCode, header};
-
-use crate::structs::Routes;
-
-pub async fn handle_get_root() -> Result<Response<Body>, Infallible> {
-    println!("->> HANDLER - handle_get_root");
-
-    //Should check for session_id because it login two times and have two sessions
-    //Should transfer to the home page if there is session
-
+use hyper::{Body, Request, Response, StatusCode, header};
+
+use crate::{
+    router::{HandlerResult, PathParams},
+    structs::{
+        AppError, Routes,
+        app_state::AppState,
+        auth::AuthenticatedUser,
+        templates::{Flash, PageContext},
+    },
+    utils::{csrf::CsrfToken, response::response_ok_with_content},
+};
+
+//Behind PageAccess::Guest, logged in users never get here
+pub async fn handle_get_root() -> Result<Response<Body>, AppError> {
     //Transfer to the login page
     let respone = Response::builder()
         .status(StatusCode::FOUND)
@@ -19,21 +22,33 @@ pub async fn handle_get_root() -> Result<Response<Body>, Infallible> {
     Ok(respone)
 }
 
-pub async fn handle_get_request(page: &str) -> Result<Response<Body>, Infallible> {
-    println!("->> HANDLER - handle_get_request - {}", page);
-
-    let page = match read_to_string(format!("./pages/{}", page)) {
-        Ok(content) => content,
-        Err(err) => {
-            //Handle the error
-            println!("->> Error in home_page {}", err);
-
-            //I should have something like basic html for error
-            "<html><body>base</body></html>".to_string()
-        }
-    };
-
-    //Should check for session id, becuase it's not appropriate to access some pages without session
+//Behind PageAccess, which already loaded the user of member pages
+pub async fn handle_get_page(
+    request: Request<Body>,
+    app_state: AppState,
+    page: &str,
+) -> Result<Response<Body>, AppError> {
+    let mut context = PageContext::new().with_flash(Flash::from_uri(request.uri()));
+    if let Some(user) = request.extensions().get::<AuthenticatedUser>() {
+        context = context.with_user(user.profile());
+    }
+    //The forms of the page send it back on every POST, PUT and DELETE
+    if let Some(token) = CsrfToken::of(&request) {
+        context = context.with_csrf_token(token.value());
+    }
+
+    let html = app_state.templates().render(page, &context)?;
+    Ok(response_ok_with_content(
+        html.into_bytes(),
+        "text/html; charset=utf-8",
+    ))
+}
 
-    Ok(Response::new(Body::from(page)))
+//Everything under Pages::STATIC_DIR, see StaticFiles
+pub async fn handle_get_static(request: Request<Body>, app_state: AppState) -> HandlerResult {
+    let path = PathParams::of(&request)
+        .and_then(|params| params.get("path"))
+        .unwrap_or_default()
+        .to_string();
+    app_state.static_files().serve(&request, &path).await
 }
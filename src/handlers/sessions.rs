@@ -1,28 +1,25 @@
-use std::convert::Infallible;
-
 use hyper::{Body, Response};
 
 use crate::{
     structs::{Routes, app_state::AppState},
-    utils::response::redirect_with_cookie,
+    utils::{
+        expired_session_cookie,
+        response::{redirect_with_cookie, redirect_without_cookie},
+    },
 };
 
 pub async fn handle_existing_session_in_login(
     app_state: &AppState,
     session_id: &str,
-) -> Result<Response<Body>, Infallible> {
+    target: &str,
+) -> Response<Body> {
     //Create respond depending on the validation of the session
-    let response = match app_state.is_session_valid(&session_id).await {
-        true => {
-            let cookie = format!("session_id={}; HttpOnly; Path=/; Max-Age=0", session_id);
-            redirect_with_cookie(&cookie, Routes::HOME, "Already logged in")
-        }
+    match app_state.is_session_valid(session_id).await {
+        //The cookie is still good, leave it alone
+        true => redirect_without_cookie(target, "Already logged in"),
         false => {
-            let cookie = format!("session_id=; HttpOnly; Path=/; Max-Age=0");
+            let cookie = expired_session_cookie(app_state.cookie_config());
             redirect_with_cookie(&cookie, Routes::LOGIN, "Invalid session")
         }
-    };
-    app_state.print_sessions().await;
-
-    Ok(response)
+    }
 }
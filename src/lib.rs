@@ -1 +1,11 @@
+pub mod cli;
+pub mod config;
+pub mod handlers;
+pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod middleware;
+pub mod router;
+pub mod shutdown;
 pub mod structs;
+pub mod utils;
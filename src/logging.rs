@@ -0,0 +1,44 @@
+use tracing::Subscriber;
+use tracing_subscriber::{EnvFilter, fmt::MakeWriter};
+
+use crate::config::{LogConfig, LogFormat, LogLevel};
+
+//What goes into a log line is decided where it is written, nothing secret is handed over:
+//requests are logged by path without the query string and never with their headers,
+//users by id, sessions only by the short hash prefix of `Display for Session`.
+//Passwords and session tokens have no Display or Debug that would show them.
+
+/// Our own crate at `level`, dependencies no chattier than `warn`.
+pub fn filter(level: LogLevel) -> EnvFilter {
+    let dependencies = level.min(LogLevel::Warn);
+    EnvFilter::new(format!("{},my_project={}", dependencies, level))
+}
+
+/// The subscriber for `config`, writing to `writer`.
+/// Every event carries the fields of the request span it happened in.
+pub fn subscriber<W>(config: &LogConfig, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter(config.level))
+        .with_writer(writer);
+
+    match config.format {
+        LogFormat::Pretty => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(
+            builder
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .finish(),
+        ),
+    }
+}
+
+/// Sends the logs of the whole process to stdout, call once at startup.
+pub fn init(config: &LogConfig) {
+    if tracing::subscriber::set_global_default(subscriber(config, std::io::stdout)).is_err() {
+        tracing::warn!("a log subscriber was already installed");
+    }
+}
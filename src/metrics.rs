@@ -0,0 +1,313 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use hyper::{Body, Method, Request, StatusCode};
+
+use crate::{
+    middleware::{Middleware, Next},
+    router::{HandlerResult, MatchedPath},
+    structs::{app_state::AppState, store::Store, traits::IntoResponse},
+};
+
+////////////////////////////////////////////////////////////////////
+pub struct MetricsConsts {}
+impl MetricsConsts {
+    pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+    /// Route label of requests no route matched.
+    pub const UNMATCHED: &str = "unmatched";
+    /// Upper bounds in seconds, from a cache hit to a slow password hash.
+    const BUCKETS: [f64; 12] = [
+        0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+    ];
+}
+
+#[derive(Clone, Default)]
+struct Histogram {
+    //Cumulative, bucket i counts everything up to BUCKETS[i]
+    buckets: [u64; MetricsConsts::BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(MetricsConsts::BUCKETS) {
+            if secs <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (bucket, bound) in self.buckets.iter().zip(MetricsConsts::BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {bucket}");
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+/// Counters and histograms for `/metrics`, in the Prometheus text format.
+///
+/// Requests are labelled by the route pattern, not the path,
+/// so `/static/{*path}` is one series however many files there are.
+/// Gauges such as the active sessions are read from the store on each scrape.
+#[derive(Default)]
+pub struct Metrics {
+    //(method, route, status)
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    //(method, route)
+    request_durations: Mutex<BTreeMap<(String, String), Histogram>>,
+    logins_succeeded: AtomicU64,
+    logins_failed: AtomicU64,
+    logins_throttled: AtomicU64,
+    registrations: AtomicU64,
+    store_durations: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe_request(
+        &self,
+        method: &Method,
+        route: &str,
+        status: StatusCode,
+        elapsed: Duration,
+    ) {
+        let method = method_label(method).to_string();
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method.clone(), route.to_string(), status.as_u16()))
+            .or_default() += 1;
+        self.request_durations
+            .lock()
+            .unwrap()
+            .entry((method, route.to_string()))
+            .or_default()
+            .observe(elapsed);
+    }
+    /// A login with the right or the wrong credentials, store failures are neither.
+    pub fn count_login(&self, succeeded: bool) {
+        let counter = match succeeded {
+            true => &self.logins_succeeded,
+            false => &self.logins_failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    /// A login refused by the limits on failed logins, before its password was checked.
+    pub fn count_throttled_login(&self) {
+        self.logins_throttled.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn count_registration(&self) {
+        self.registrations.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn observe_store(&self, operation: &'static str, elapsed: Duration) {
+        self.store_durations
+            .lock()
+            .unwrap()
+            .entry(operation)
+            .or_default()
+            .observe(elapsed);
+    }
+
+    /// Everything in the text exposition format, `store` is asked for the gauges.
+    pub async fn render(&self, store: &dyn Store) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "my_project_http_requests_total",
+            "counter",
+            "HTTP requests answered.",
+        );
+        for ((method, route, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "my_project_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape(route),
+                status,
+                count
+            );
+        }
+
+        let name = "my_project_http_request_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Time to answer an HTTP request.",
+        );
+        for ((method, route), histogram) in self.request_durations.lock().unwrap().iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+            histogram.write(&mut out, name, &labels);
+        }
+
+        header(
+            &mut out,
+            "my_project_logins_total",
+            "counter",
+            "Login attempts by result.",
+        );
+        let _ = writeln!(
+            out,
+            "my_project_logins_total{{result=\"success\"}} {}",
+            self.logins_succeeded.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "my_project_logins_total{{result=\"failure\"}} {}",
+            self.logins_failed.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "my_project_logins_total{{result=\"throttled\"}} {}",
+            self.logins_throttled.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "my_project_registrations_total",
+            "counter",
+            "Users registered.",
+        );
+        let _ = writeln!(
+            out,
+            "my_project_registrations_total {}",
+            self.registrations.load(Ordering::Relaxed)
+        );
+
+        //Expired sessions count until the reaper or a visit drops them
+        if let Ok(sessions) = store.session_count().await {
+            header(
+                &mut out,
+                "my_project_active_sessions",
+                "gauge",
+                "Sessions in the store.",
+            );
+            let _ = writeln!(out, "my_project_active_sessions {}", sessions);
+        }
+
+        let name = "my_project_store_operation_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Time spent in the store per operation.",
+        );
+        for (operation, histogram) in self.store_durations.lock().unwrap().iter() {
+            histogram.write(&mut out, name, &format!("operation=\"{}\"", operation));
+        }
+
+        if let Some(pool) = store.sql_pool() {
+            let size = pool.size();
+            let idle = pool.num_idle() as u32;
+            header(
+                &mut out,
+                "my_project_db_pool_connections",
+                "gauge",
+                "Open database connections.",
+            );
+            let _ = writeln!(
+                out,
+                "my_project_db_pool_connections{{state=\"idle\"}} {}",
+                idle
+            );
+            let _ = writeln!(
+                out,
+                "my_project_db_pool_connections{{state=\"in_use\"}} {}",
+                size.saturating_sub(idle)
+            );
+            header(
+                &mut out,
+                "my_project_db_pool_max_connections",
+                "gauge",
+                "Pool size limit.",
+            );
+            let _ = writeln!(
+                out,
+                "my_project_db_pool_max_connections {}",
+                pool.options().get_max_connections()
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+//Label values are quoted, these three would end them early
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+//Made up methods would each add a series
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+/// Counts every request with its route and status and times it,
+/// when the `AppState` has metrics.
+pub struct RecordMetrics;
+
+#[async_trait]
+impl Middleware for RecordMetrics {
+    async fn handle(
+        &self,
+        request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let Some(metrics) = app_state.metrics().cloned() else {
+            return next.run(request, app_state).await;
+        };
+        let method = request.method().clone();
+        let started = Instant::now();
+
+        let response = next.run(request, app_state).await.into_response();
+
+        let route = MatchedPath::of(&response)
+            .map(MatchedPath::as_str)
+            .unwrap_or(MetricsConsts::UNMATCHED);
+        metrics.observe_request(&method, route, response.status(), started.elapsed());
+        Ok(response)
+    }
+}
@@ -0,0 +1,378 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use hyper::{
+    Body, Method, Request, Response,
+    header::{self, HeaderName, HeaderValue},
+};
+use rand::RngCore;
+use tracing::{Instrument, field};
+
+use crate::{
+    router::{Handler, HandlerResult, Router},
+    structs::{
+        AppError,
+        app_state::AppState,
+        auth::{AuthenticatedUser, wants_html},
+        traits::IntoResponse,
+    },
+    utils::{
+        csrf::{CsrfConsts, CsrfToken, csrf_cookie, submitted_token, tokens_match},
+        expired_session_cookie, extract_session_id_from_header,
+        redirect::{after_login_target, login_redirect_target},
+        response::redirect_without_cookie,
+    },
+};
+
+/// Code that runs around a handler, e.g. logging or authentication.
+///
+/// Call `next.run(...)` to continue down the chain or return early to short-circuit it.
+/// Attach with `Router::layer` for the routes registered so far (a route group)
+/// or `Router::around` for every request, including 404 and 405 answers.
+#[async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    async fn handle(
+        &self,
+        request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult;
+}
+
+pub(crate) enum Endpoint<'a> {
+    Router(&'a Router),
+    Handler(&'a Handler),
+}
+
+/// The rest of the middleware chain, ending in the handler.
+pub struct Next<'a> {
+    pub(crate) remaining: &'a [Arc<dyn Middleware>],
+    pub(crate) endpoint: Endpoint<'a>,
+}
+
+impl<'a> Next<'a> {
+    pub fn run(
+        self,
+        request: Request<Body>,
+        app_state: AppState,
+    ) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + 'a>> {
+        Box::pin(async move {
+            match self.remaining.split_first() {
+                Some((first, rest)) => {
+                    let next = Next {
+                        remaining: rest,
+                        endpoint: self.endpoint,
+                    };
+                    first.handle(request, app_state, next).await
+                }
+                None => match self.endpoint {
+                    Endpoint::Router(router) => Ok(router.route_request(request, app_state).await),
+                    Endpoint::Handler(handler) => handler(request, app_state).await,
+                },
+            }
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+/// Runs each request in a `request` span with a fresh request id, method and path,
+/// plus the user id once a session or login resolves it.
+/// Ends with one line holding the status and latency.
+///
+/// The id is also sent back in `x-request-id` to find the logs of a response.
+/// The query string is left out, it may carry more than a path should.
+pub struct RequestLog;
+
+impl RequestLog {
+    pub const REQUEST_ID_HEADER: &'static str = "x-request-id";
+}
+
+#[async_trait]
+impl Middleware for RequestLog {
+    async fn handle(
+        &self,
+        request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let request_id = new_request_id();
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            method = %request.method(),
+            path = %request.uri().path(),
+            user_id = field::Empty,
+        );
+        let started = Instant::now();
+
+        let mut response = next
+            .run(request, app_state)
+            .instrument(span.clone())
+            .await
+            .into_response();
+
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        span.in_scope(|| {
+            tracing::info!(status = response.status().as_u16(), latency_ms, "finished");
+        });
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response
+                .headers_mut()
+                .insert(Self::REQUEST_ID_HEADER, value);
+        }
+        Ok(response)
+    }
+}
+
+fn new_request_id() -> String {
+    let mut bytes = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Reports how long the handler took in a `Server-Timing` header.
+pub struct Timing;
+
+#[async_trait]
+impl Middleware for Timing {
+    async fn handle(
+        &self,
+        request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let started = Instant::now();
+        let mut response = next.run(request, app_state).await.into_response();
+
+        let millis = started.elapsed().as_secs_f64() * 1000.0;
+        if let Ok(value) = HeaderValue::from_str(&format!("app;dur={:.2}", millis)) {
+            response.headers_mut().insert("server-timing", value);
+        }
+        Ok(response)
+    }
+}
+
+/// Adds fixed headers to every response, errors included.
+/// Headers the handler already set are left alone.
+pub struct SetHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SetHeaders {
+    pub fn new() -> Self {
+        Self {
+            headers: Vec::new(),
+        }
+    }
+    pub fn header(mut self, name: &'static str, value: &'static str) -> Self {
+        self.headers.push((
+            HeaderName::from_static(name),
+            HeaderValue::from_static(value),
+        ));
+        self
+    }
+    /// Conservative defaults for a server that only serves its own pages.
+    pub fn security() -> Self {
+        Self::new()
+            .header("x-content-type-options", "nosniff")
+            .header("x-frame-options", "DENY")
+            .header("referrer-policy", "same-origin")
+    }
+}
+
+impl Default for SetHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Middleware for SetHeaders {
+    async fn handle(
+        &self,
+        request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let mut response = next.run(request, app_state).await.into_response();
+
+        for (name, value) in &self.headers {
+            if !response.headers().contains_key(name) {
+                response.headers_mut().insert(name.clone(), value.clone());
+            }
+        }
+        Ok(response)
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+/// Lets only requests with a live session through and hands the handler
+/// an `AuthenticatedUser`, read back with `AuthenticatedUser::of`.
+///
+/// Everyone else gets a 302 to the login page when a browser asked for a page
+/// and a 401 problem otherwise. A stale cookie is cleared either way.
+pub struct RequireAuth;
+
+#[async_trait]
+impl Middleware for RequireAuth {
+    async fn handle(
+        &self,
+        mut request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let user = match AuthenticatedUser::from_request(&request, &app_state).await {
+            Ok(user) => user,
+            Err(AppError::Unauthorized(msg)) => {
+                return Ok(unauthenticated(&request, &app_state, &msg));
+            }
+            Err(err) => return Err(err),
+        };
+
+        request.extensions_mut().insert(user);
+        next.run(request, app_state).await
+    }
+}
+
+fn unauthenticated(request: &Request<Body>, app_state: &AppState, msg: &str) -> Response<Body> {
+    if wants_html(request) {
+        let target = login_redirect_target(request.uri());
+        return unauthenticated_redirect(request, app_state, &target);
+    }
+    clear_stale_cookie(
+        request,
+        app_state,
+        AppError::Unauthorized(msg.to_string()).into_response(),
+    )
+}
+
+fn unauthenticated_redirect(
+    request: &Request<Body>,
+    app_state: &AppState,
+    target: &str,
+) -> Response<Body> {
+    let response = redirect_without_cookie(target, "Please log in");
+    clear_stale_cookie(request, app_state, response)
+}
+
+fn clear_stale_cookie(
+    request: &Request<Body>,
+    app_state: &AppState,
+    mut response: Response<Body>,
+) -> Response<Body> {
+    if request.headers().contains_key(header::COOKIE)
+        && let Ok(cookie) =
+            HeaderValue::from_str(&expired_session_cookie(app_state.cookie_config()))
+    {
+        response.headers_mut().insert(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+////////////////////////////////////////////////////////////////////
+/// Who may see an HTML page.
+pub enum PageAccess {
+    /// Anonymous visitors go to `/login?next=<this page>`.
+    /// Logged in users reach the page with an `AuthenticatedUser` in the extensions.
+    LoggedIn,
+    /// Logged in users are sent on to `next` or `/home`, e.g. for the login and register pages.
+    Guest,
+}
+
+#[async_trait]
+impl Middleware for PageAccess {
+    async fn handle(
+        &self,
+        mut request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let user = match AuthenticatedUser::from_request(&request, &app_state).await {
+            Ok(user) => Some(user),
+            Err(AppError::Unauthorized(_)) => None,
+            Err(err) => return Err(err),
+        };
+
+        match (self, user) {
+            (PageAccess::LoggedIn, Some(user)) => {
+                request.extensions_mut().insert(user);
+                next.run(request, app_state).await
+            }
+            (PageAccess::LoggedIn, None) => {
+                let target = login_redirect_target(request.uri());
+                Ok(unauthenticated_redirect(&request, &app_state, &target))
+            }
+            (PageAccess::Guest, Some(_)) => {
+                let target = after_login_target(request.uri());
+                Ok(redirect_without_cookie(&target, "Already logged in"))
+            }
+            (PageAccess::Guest, None) => next.run(request, app_state).await,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+/// Answers POST, PUT, DELETE and the like with a 403 unless they send back
+/// the CSRF token of their page, in `X-CSRF-Token` or a `csrf_token` form field,
+/// equal to the signed `csrf_token` cookie of the same session.
+///
+/// Every request gets a `CsrfToken` in its extensions for the page to embed.
+/// A new token is only set as a cookie when a handler read it,
+/// so static files and redirects don't carry one.
+pub struct CsrfProtection;
+
+#[async_trait]
+impl Middleware for CsrfProtection {
+    async fn handle(
+        &self,
+        mut request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let key = app_state.csrf_key();
+        //Bound to the session, a token from before logging in or out is no good after it
+        let cookie_config = app_state.cookie_config().clone();
+        let session =
+            extract_session_id_from_header(request.headers(), &cookie_config).unwrap_or_default();
+        let cookie_token = cookie_config
+            .read(request.headers(), CsrfConsts::COOKIE)
+            .filter(|token| key.verify(token, &session));
+
+        if !is_safe(request.method()) {
+            let submitted;
+            (request, submitted) = submitted_token(request).await?;
+            let valid = matches!(
+                (&cookie_token, &submitted),
+                (Some(cookie), Some(sent)) if tokens_match(cookie, sent)
+            );
+            if !valid {
+                tracing::warn!("rejected a request without a valid CSRF token");
+                return Err(AppError::Forbidden(
+                    "Missing or invalid CSRF token".to_string(),
+                ));
+            }
+        }
+
+        let fresh = cookie_token.is_none();
+        let token = CsrfToken::new(cookie_token.unwrap_or_else(|| key.issue(&session)));
+        request.extensions_mut().insert(token.clone());
+
+        let mut response = next.run(request, app_state).await.into_response();
+        if fresh
+            && token.was_used()
+            && let Ok(cookie) = HeaderValue::from_str(&csrf_cookie(token.value(), &cookie_config))
+        {
+            //Appended, the handler may be setting the session cookie too
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+        Ok(response)
+    }
+}
+
+//Methods that must not change anything, so a forged one does no harm
+fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
@@ -0,0 +1,399 @@
+use std::{collections::HashMap, future::Future, net::IpAddr, pin::Pin, str::FromStr, sync::Arc};
+
+use hyper::{Body, Method, Request, Response, StatusCode, header};
+
+use crate::{
+    middleware::{Endpoint, Middleware, Next},
+    structs::{AppError, app_state::AppState, traits::IntoResponse},
+};
+
+pub type HandlerResult = Result<Response<Body>, AppError>;
+type HandlerFuture<'a> = Pin<Box<dyn Future<Output = HandlerResult> + Send + 'a>>;
+pub(crate) type Handler =
+    Arc<dyn Fn(Request<Body>, AppState) -> HandlerFuture<'static> + Send + Sync>;
+
+////////////////////////////////////////////////////////////////////
+/// Values captured from `{name}` and `{*name}` segments of the matched pattern.
+/// The router stores them in the request extensions before calling the handler.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathParams(HashMap<String, String>);
+
+impl PathParams {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+    /// Parses a parameter, a missing or malformed one is a `BadRequest`.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, AppError> {
+        self.get(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| AppError::BadRequest(format!("Invalid path parameter {}", name)))
+    }
+    /// The parameters of an already routed request.
+    pub fn of(request: &Request<Body>) -> Option<&PathParams> {
+        request.extensions().get::<PathParams>()
+    }
+}
+
+/// The pattern a request was routed by, e.g. `/static/{*path}`.
+/// Stored in the response extensions, so middleware around the router
+/// can group requests by route without one entry per distinct path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchedPath(String);
+
+impl MatchedPath {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// `None` when no route matched, i.e. for 404 answers.
+    pub fn of(response: &Response<Body>) -> Option<&MatchedPath> {
+        response.extensions().get::<MatchedPath>()
+    }
+}
+
+/// Address of the peer a request came from, put in the request extensions
+/// by the server. Behind a proxy that is the proxy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientAddr(IpAddr);
+
+impl ClientAddr {
+    pub fn new(ip: IpAddr) -> Self {
+        Self(ip)
+    }
+    pub fn ip(&self) -> IpAddr {
+        self.0
+    }
+    /// `None` for requests that didn't come over a socket, e.g. in tests.
+    pub fn of(request: &Request<Body>) -> Option<IpAddr> {
+        request.extensions().get::<ClientAddr>().map(ClientAddr::ip)
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    //Swallows the rest of the path, only allowed last
+    Wildcard(String),
+}
+
+#[derive(Debug)]
+struct Pattern {
+    segments: Vec<Segment>,
+    source: String,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Self {
+        let segments: Vec<Segment> = split_path(pattern)
+            .map(|segment| {
+                match segment
+                    .strip_prefix('{')
+                    .and_then(|inner| inner.strip_suffix('}'))
+                {
+                    Some(inner) => match inner.strip_prefix('*') {
+                        Some(name) => Segment::Wildcard(name.to_string()),
+                        None => Segment::Param(inner.to_string()),
+                    },
+                    None => Segment::Literal(segment.to_string()),
+                }
+            })
+            .collect();
+
+        let wildcard_before_end = segments
+            .iter()
+            .rev()
+            .skip(1)
+            .any(|segment| matches!(segment, Segment::Wildcard(_)));
+        assert!(
+            !wildcard_before_end,
+            "Wildcard must be the last segment of {}",
+            pattern
+        );
+
+        Self {
+            segments,
+            source: pattern.to_string(),
+        }
+    }
+
+    fn matches(&self, path: &str) -> Option<PathParams> {
+        let mut params = HashMap::new();
+        let mut parts = split_path(path);
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Wildcard(name) => {
+                    let rest: Vec<&str> = parts.by_ref().collect();
+                    params.insert(name.clone(), rest.join("/"));
+                    return Some(PathParams(params));
+                }
+                Segment::Literal(literal) => {
+                    if parts.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), parts.next()?.to_string());
+                }
+            }
+        }
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(PathParams(params)),
+        }
+    }
+
+    //Literal segments win over parameters, parameters over wildcards,
+    //so `/profile/user` is picked before `/profile/{id}`
+    fn specificity(&self) -> (usize, usize) {
+        let literals = self
+            .segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Literal(_)))
+            .count();
+        let params = self
+            .segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Param(_)))
+            .count();
+        (literals, params)
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+////////////////////////////////////////////////////////////////////
+struct MethodHandler {
+    method: Method,
+    handler: Handler,
+    //Outermost first
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl MethodHandler {
+    fn call(&self, request: Request<Body>, app_state: AppState) -> HandlerFuture<'_> {
+        Next {
+            remaining: &self.middlewares,
+            endpoint: Endpoint::Handler(&self.handler),
+        }
+        .run(request, app_state)
+    }
+}
+
+struct Route {
+    pattern: Pattern,
+    handlers: Vec<MethodHandler>,
+}
+
+impl Route {
+    fn handler(&self, method: &Method) -> Option<&MethodHandler> {
+        self.handlers
+            .iter()
+            .find(|registered| registered.method == method)
+    }
+    /// Methods for the `Allow` header, HEAD and OPTIONS come for free.
+    fn allowed_methods(&self) -> Vec<Method> {
+        let mut allowed: Vec<Method> = self
+            .handlers
+            .iter()
+            .map(|registered| registered.method.clone())
+            .collect();
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
+        }
+        if !allowed.contains(&Method::OPTIONS) {
+            allowed.push(Method::OPTIONS);
+        }
+        allowed
+    }
+}
+
+/// Maps method and path pairs to handlers.
+///
+/// Unknown paths get a 404, known paths with the wrong method a 405 with `Allow`.
+/// `HEAD` falls back to the `GET` handler without the body
+/// and `OPTIONS` is answered with the allowed methods.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    //Around the whole dispatch, outermost first
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler` for `method` on `pattern`, e.g. `/users/{id}` or `/static/{*path}`.
+    pub fn route<H, Fut>(mut self, method: Method, pattern: &str, handler: H) -> Self
+    where
+        H: Fn(Request<Body>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |request, app_state| {
+            Box::pin(handler(request, app_state)) as HandlerFuture<'static>
+        });
+
+        self.insert(
+            Pattern::parse(pattern),
+            MethodHandler {
+                method,
+                handler,
+                middlewares: Vec::new(),
+            },
+        );
+        self
+    }
+    fn insert(&mut self, pattern: Pattern, handler: MethodHandler) {
+        match self
+            .routes
+            .iter_mut()
+            .find(|route| route.pattern.segments == pattern.segments)
+        {
+            Some(route) => route.handlers.push(handler),
+            None => self.routes.push(Route {
+                pattern,
+                handlers: vec![handler],
+            }),
+        }
+    }
+    pub fn get<H, Fut>(self, pattern: &str, handler: H) -> Self
+    where
+        H: Fn(Request<Body>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.route(Method::GET, pattern, handler)
+    }
+    pub fn post<H, Fut>(self, pattern: &str, handler: H) -> Self
+    where
+        H: Fn(Request<Body>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.route(Method::POST, pattern, handler)
+    }
+    pub fn put<H, Fut>(self, pattern: &str, handler: H) -> Self
+    where
+        H: Fn(Request<Body>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.route(Method::PUT, pattern, handler)
+    }
+    pub fn delete<H, Fut>(self, pattern: &str, handler: H) -> Self
+    where
+        H: Fn(Request<Body>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.route(Method::DELETE, pattern, handler)
+    }
+
+    /// Wraps every route registered so far, later layers run first.
+    /// Build a route group as its own router, layer it and `merge` it in.
+    pub fn layer(mut self, middleware: impl Middleware) -> Self {
+        let middleware: Arc<dyn Middleware> = Arc::new(middleware);
+        for route in &mut self.routes {
+            for handler in &mut route.handlers {
+                handler.middlewares.insert(0, middleware.clone());
+            }
+        }
+        self
+    }
+    /// Wraps the whole dispatch, so it also sees 404, 405 and OPTIONS answers.
+    pub fn around(mut self, middleware: impl Middleware) -> Self {
+        self.middlewares.insert(0, Arc::new(middleware));
+        self
+    }
+    /// Takes over the routes of `other` together with their layers.
+    /// Its `around` middlewares are not carried over.
+    pub fn merge(mut self, other: Router) -> Self {
+        for route in other.routes {
+            for handler in route.handlers {
+                let pattern = Pattern {
+                    segments: route.pattern.segments.iter().map(Segment::clone).collect(),
+                    source: route.pattern.source.clone(),
+                };
+                self.insert(pattern, handler);
+            }
+        }
+        self
+    }
+
+    fn find_route(&self, path: &str) -> Option<(&Route, PathParams)> {
+        self.routes
+            .iter()
+            .filter_map(|route| route.pattern.matches(path).map(|params| (route, params)))
+            //max_by_key keeps the last maximum, reversing keeps the first registered
+            .rev()
+            .max_by_key(|(route, _)| route.pattern.specificity())
+    }
+
+    /// Runs the handler for `request` through every middleware,
+    /// whatever error is left is turned into its response here.
+    pub async fn dispatch(&self, request: Request<Body>, app_state: AppState) -> Response<Body> {
+        Next {
+            remaining: &self.middlewares,
+            endpoint: Endpoint::Router(self),
+        }
+        .run(request, app_state)
+        .await
+        .into_response()
+    }
+
+    pub(crate) async fn route_request(
+        &self,
+        mut request: Request<Body>,
+        app_state: AppState,
+    ) -> Response<Body> {
+        let method = request.method().clone();
+
+        let Some((route, params)) = self.find_route(request.uri().path()) else {
+            let path = request.uri().path().to_string();
+            return AppError::NotFound(format!("No route for {}", path)).into_response();
+        };
+        request.extensions_mut().insert(params);
+        let matched = MatchedPath(route.pattern.source.clone());
+
+        let mut response = match route.handler(&method) {
+            Some(handler) => handler.call(request, app_state).await.into_response(),
+            None => Self::answer_unhandled(route, method, request, app_state).await,
+        };
+        response.extensions_mut().insert(matched);
+        response
+    }
+
+    //HEAD without its own handler, OPTIONS and wrong methods
+    async fn answer_unhandled(
+        route: &Route,
+        method: Method,
+        request: Request<Body>,
+        app_state: AppState,
+    ) -> Response<Body> {
+        match method {
+            Method::HEAD if route.handler(&Method::GET).is_some() => {
+                let handler = route.handler(&Method::GET).unwrap();
+                let response = handler.call(request, app_state).await.into_response();
+                //Same status and headers as GET, without the body
+                let (parts, _body) = response.into_parts();
+                Response::from_parts(parts, Body::empty())
+            }
+            Method::OPTIONS => Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header(header::ALLOW, allow_header(&route.allowed_methods()))
+                .body(Body::empty())
+                .unwrap(),
+            _ => AppError::MethodNotAllowed(route.allowed_methods()).into_response(),
+        }
+    }
+}
+
+pub fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
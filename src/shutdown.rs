@@ -0,0 +1,136 @@
+use std::{fmt::Display, future::Future, pin::Pin, time::Duration};
+
+use tokio::sync::oneshot;
+
+/// Process exit codes of the server.
+pub struct ExitCodes;
+
+impl ExitCodes {
+    /// Stopped by a signal after every request finished.
+    pub const OK: i32 = 0;
+    /// Couldn't start or the server failed while running.
+    pub const FAILURE: i32 = 1;
+    /// The configuration is invalid, nothing was started.
+    pub const CONFIG: i32 = 2;
+    /// Stopped by a signal, but requests were still running at the deadline.
+    pub const DRAIN_TIMEOUT: i32 = 3;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// Ctrl-C or SIGINT.
+    Interrupt,
+    /// SIGTERM, e.g. from an orchestrator stopping the container.
+    Terminate,
+}
+
+impl Display for ShutdownReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShutdownReason::Interrupt => write!(f, "SIGINT"),
+            ShutdownReason::Terminate => write!(f, "SIGTERM"),
+        }
+    }
+}
+
+/// Resolves on the first Ctrl-C, or SIGTERM on unix.
+pub async fn shutdown_signal() -> ShutdownReason {
+    let interrupt = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            //No handler means no way to be told, wait for the other signal
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => ShutdownReason::Interrupt,
+        _ = terminate => ShutdownReason::Terminate,
+    }
+}
+
+/// How `serve_until` ended.
+#[derive(Debug)]
+pub enum Outcome {
+    /// Every in-flight request finished before the deadline.
+    Drained,
+    /// Requests were still running when the deadline hit.
+    /// Their connections live on their own tasks, exiting the process cuts them.
+    DeadlineExceeded,
+    Failed(hyper::Error),
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Drained => ExitCodes::OK,
+            Outcome::DeadlineExceeded => ExitCodes::DRAIN_TIMEOUT,
+            Outcome::Failed(_) => ExitCodes::FAILURE,
+        }
+    }
+
+    /// How long closing the store may take afterwards, out of the same `deadline`.
+    ///
+    /// Requests cut at the deadline still hold their pool connections and closing would
+    /// wait for them forever, so then the store is only told to close.
+    pub fn close_timeout(&self, deadline: Duration) -> Duration {
+        match self {
+            Outcome::DeadlineExceeded => Duration::ZERO,
+            Outcome::Drained | Outcome::Failed(_) => deadline,
+        }
+    }
+}
+
+/// A future that tells the server to stop accepting connections.
+pub type StopSignal = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs the server built by `serve` until `signal` resolves, then stops
+/// accepting and gives the requests in flight `deadline` to finish.
+///
+/// `serve` gets the future to hand to `Server::with_graceful_shutdown`.
+pub async fn serve_until<F, S>(
+    serve: impl FnOnce(StopSignal) -> F,
+    signal: S,
+    deadline: Duration,
+) -> Outcome
+where
+    F: Future<Output = hyper::Result<()>>,
+    S: Future<Output = ShutdownReason>,
+{
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = serve(Box::pin(async move {
+        let _ = stopped.await;
+    }));
+    tokio::pin!(server);
+
+    let reason = tokio::select! {
+        result = &mut server => {
+            return match result {
+                Ok(()) => Outcome::Drained,
+                Err(err) => Outcome::Failed(err),
+            };
+        }
+        reason = signal => reason,
+    };
+
+    tracing::info!(%reason, ?deadline, "draining in-flight requests");
+    let _ = stop.send(());
+
+    match tokio::time::timeout(deadline, server).await {
+        Ok(Ok(())) => Outcome::Drained,
+        Ok(Err(err)) => Outcome::Failed(err),
+        Err(_) => Outcome::DeadlineExceeded,
+    }
+}
@@ -1,236 +1,442 @@
-use std::sync::Arc;
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use sqlx::MySqlPool;
-use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::Span;
 
-use crate::structs::{login::LoginInfo, session::Session, user::{StoredUser, User, UserProfile}, AppError};
+use crate::{
+    config::{Config, StorageBackend},
+    structs::{
+        AppError, Pages,
+        assets::Assets,
+        auth::AuthenticatedUser,
+        login::LoginInfo,
+        password::{
+            PasswordCheck, PasswordPolicy, exceeds_length_ceiling, spawn_hash_password,
+            spawn_verify_dummy_password,
+        },
+        session::{Session, SessionConfig},
+        store::{CachedStore, MemoryStore, SqlStore, Store},
+        templates::Templates,
+        throttle::{LoginAttempts, LoginLimits},
+        user::{User, UserProfile},
+    },
+    utils::{cookie::CookieConfig, csrf::CsrfKey, static_files::StaticFiles},
+};
+#[cfg(feature = "metrics")]
+use crate::{metrics::Metrics, structs::store::MeteredStore};
 
 #[derive(Clone)]
 pub struct AppState {
-    users: Arc<Mutex<Vec<StoredUser>>>,
-    sessions: Arc<Mutex<Vec<Session>>>,
-    db: Option<MySqlPool>,
+    store: Arc<dyn Store>,
+    session_config: SessionConfig,
+    cookie_config: CookieConfig,
+    csrf_key: Arc<CsrfKey>,
+    password_policy: PasswordPolicy,
+    login_limits: LoginLimits,
+    templates: Arc<Templates>,
+    static_files: Arc<StaticFiles>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl AppState {
+    /// State backed by the database at `db_url`, every lookup reads it
+    /// so accounts and sessions survive restarts.
     pub async fn new(db_url: &str) -> Result<Self, sqlx::Error> {
-        let db_pool = MySqlPool::connect(db_url).await;
-
-        match db_pool {
-            Ok(db) => Ok(Self {
-                users: Arc::new(Mutex::new(Vec::new())),
-                sessions: Arc::new(Mutex::new(Vec::new())),
-                db: Some(db),
-            }),
-            Err(error) => Err(error),
-        }
+        let store = SqlStore::connect(db_url).await?;
+        Ok(Self::from_store(Arc::new(store)))
     }
-    pub fn new_without_db() -> Result<Self, sqlx::Error> {
-        Ok(Self {
-                users: Arc::new(Mutex::new(Vec::new())),
-                sessions: Arc::new(Mutex::new(Vec::new())),
-                db: None,
-            })
+    /// Like `new`, with user lookups cached in memory.
+    /// Use only when this process is the single writer of the database.
+    pub async fn new_cached(db_url: &str) -> Result<Self, sqlx::Error> {
+        let store = SqlStore::connect(db_url).await?;
+        Ok(Self::from_store(Arc::new(CachedStore::new(store))))
     }
-    pub async fn add_user(&self, user: User) -> Result<(), AppError> {
-        println!("->> HANDLER - add_user");
+    /// State with the store and settings `config` asks for.
+    pub async fn from_config(config: &Config) -> Result<Self, sqlx::Error> {
+        let app_state = match config.storage.backend {
+            StorageBackend::Memory => Self::new_in_memory(),
+            StorageBackend::Sql => Self::new(&config.storage.url).await?,
+            StorageBackend::SqlCached => Self::new_cached(&config.storage.url).await?,
+        };
 
-        if let Err(err) = user.validate() {
-            println!("->> ERROR - cannot add user {}", err);
-        }
+        let csrf_key = match &config.cookie.secret {
+            Some(secret) => CsrfKey::from_secret(secret.expose()),
+            None => CsrfKey::random(),
+        };
+        let app_state = app_state
+            .with_session_config(config.session.session_config())
+            .with_csrf_key(csrf_key)
+            .with_cookie_config(config.cookie.clone())
+            .with_password_policy(config.password.clone())
+            .with_login_limits(config.login.clone())
+            .with_assets(Assets::select(config.server.pages_dir.clone()));
+        //Only an explicit pages_dir is watched for edits, the walk costs every render
+        let app_state = match &config.server.pages_dir {
+            Some(dir) => app_state.with_templates(Templates::from_dir(dir.clone())),
+            None => app_state,
+        };
 
-        let mut users = self.users.lock().await;
-
-        let users_len = users.len();
-        let user_for_db = user.clone();
-
-        let new_stored_user = StoredUser::new(users_len, user).map_err(|err_msg| AppError::UserError(err_msg))?;
-        users.push(new_stored_user);
-
-        //add user to the data base
-        match &self.db {
-            Some(pool) => {
-                sqlx::query(
-            "INSERT INTO users (id, first_name, last_name, email, password) VALUES (?, ?, ?, ?, ?)",
-                )
-                .bind(&users_len.to_string())
-                .bind(&user_for_db.first_name())
-                .bind(&user_for_db.last_name())
-                .bind(&user_for_db.email())
-                .bind(&user_for_db.password())
-                .execute(pool)
-                .await
-                .map_err(|e| {
-                    println!("Error inserting user into DB: {}", e);
-                    e
-                })?;
-            }
-            None => {
-                println!("->>Error with accessing the db");
-                //print the user_count
-                self.print_db_user_count().await;
-            }
+        #[cfg(feature = "metrics")]
+        if config.metrics.enabled {
+            return Ok(app_state.with_metrics(Arc::new(Metrics::new())));
         }
-
-        Ok(())
+        Ok(app_state)
     }
-    pub async fn print_db_user_count(&self) {
-        match &self.db {
-            Some(pool) => {
-                let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
-                    .fetch_one(pool)
-                    .await
-                    .unwrap();
-
-                println!("Total users in db: {}", row.0);
-            }
-            None => {
-                println!("->>Error with accessing the db");
-            }
+    /// State backed by a `MemoryStore`, nothing is persisted.
+    pub fn new_in_memory() -> Self {
+        Self::from_store(Arc::new(MemoryStore::new()))
+    }
+    /// State backed by any store, e.g. a fake one in tests.
+    pub fn from_store(store: Arc<dyn Store>) -> Self {
+        Self {
+            store,
+            session_config: SessionConfig::default(),
+            cookie_config: CookieConfig::default(),
+            csrf_key: Arc::new(CsrfKey::random()),
+            password_policy: PasswordPolicy::default(),
+            login_limits: LoginLimits::default(),
+            templates: Arc::new(Templates::from_assets(Assets::default())),
+            static_files: Arc::new(StaticFiles::from_assets(
+                Assets::default().join(Pages::STATIC_DIR),
+            )),
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
-    pub async fn update_user(&self, updated_user: User, target_id: usize) -> Result<(), String> {
-        println!("->> HANDLER - update_user");
+    pub fn with_session_config(mut self, session_config: SessionConfig) -> Self {
+        self.session_config = session_config;
+        self
+    }
+    pub fn session_config(&self) -> &SessionConfig {
+        &self.session_config
+    }
+    pub fn with_cookie_config(mut self, cookie_config: CookieConfig) -> Self {
+        self.cookie_config = cookie_config;
+        self
+    }
+    pub fn cookie_config(&self) -> &CookieConfig {
+        &self.cookie_config
+    }
+    pub fn with_csrf_key(mut self, csrf_key: CsrfKey) -> Self {
+        self.csrf_key = Arc::new(csrf_key);
+        self
+    }
+    pub fn csrf_key(&self) -> &CsrfKey {
+        &self.csrf_key
+    }
+    pub fn with_password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = password_policy;
+        self
+    }
+    pub fn password_policy(&self) -> &PasswordPolicy {
+        &self.password_policy
+    }
+    pub fn with_login_limits(mut self, login_limits: LoginLimits) -> Self {
+        self.login_limits = login_limits;
+        self
+    }
+    pub fn login_limits(&self) -> &LoginLimits {
+        &self.login_limits
+    }
+    /// Pages and static files both from `assets`.
+    pub fn with_assets(self, assets: Assets) -> Self {
+        let static_files = StaticFiles::from_assets(assets.join(Pages::STATIC_DIR));
+        self.with_templates(Templates::from_assets(assets))
+            .with_static_files(static_files)
+    }
+    pub fn with_templates(mut self, templates: Templates) -> Self {
+        self.templates = Arc::new(templates);
+        self
+    }
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+    pub fn with_static_files(mut self, static_files: StaticFiles) -> Self {
+        self.static_files = Arc::new(static_files);
+        self
+    }
+    pub fn static_files(&self) -> &StaticFiles {
+        &self.static_files
+    }
+    pub fn store(&self) -> &Arc<dyn Store> {
+        &self.store
+    }
+    /// Turns on `/metrics`, the store is wrapped to time its calls.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.store = Arc::new(MeteredStore::new(self.store, metrics.clone()));
+        self.metrics = Some(metrics);
+        self
+    }
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
+    }
+    pub async fn add_user(&self, user: User) -> Result<(), AppError> {
+        let mut report = user.validation_report();
+        self.password_policy.check(&mut report, user.password());
+        report.into_result()?;
 
-        if let Err(err) = updated_user.validate() {
-            return Err(format!("->> ERROR - cannot update user {}", err));
+        //Cheap early answer, the store still enforces uniqueness on insert
+        let user = user.with_normalized_email();
+        if self.store.find_user_by_email(user.email()).await?.is_some() {
+            return Err(AppError::Conflict(
+                "Email is already registered".to_string(),
+            ));
         }
 
-        let mut users = self.users.lock().await;
-        let user_for_db = updated_user.clone();
-
-        //Searching for target user to update
-        if let Some(user) = users.iter_mut().find(|u| u.user_id() == target_id) {
-            user.get_base_mut().copy_operator(&updated_user);
-            println!("User updated.");
-        } else {
-            return Err(format!("->> Error - User not found."));
+        //The password is hashed here, only the hash is kept and persisted
+        let password_hash = spawn_hash_password(user.password()).await?;
+        let stored = self.store.insert_user(&user, &password_hash).await?;
+        tracing::info!(user_id = stored.user_id(), "user registered");
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.count_registration();
         }
 
-        //update in the database
-        match &self.db {
-            Some(pool) => {
-
-                //should fix the unwrap here to someting smarter
-                sqlx::query(
-                    "UPDATE users SET first_name = ?, last_name = ?, email = ?, password = ? WHERE id = ?"
-                )
-                .bind(&user_for_db.first_name())
-                .bind(&user_for_db.last_name())
-                .bind(&user_for_db.email())
-                .bind(&user_for_db.password())
-                .bind(target_id.to_string())
-                .execute(pool)
-                .await
-                .map_err(|e| {
-                    format!("Error updating user into DB: {}", e)
-                     
-                }).unwrap();
-            }
-            None => {
-                println!("->>Error with accessing the db");
-            }
-        }
+        Ok(())
+    }
+    pub async fn update_user(&self, updated_user: User, target_id: usize) -> Result<(), AppError> {
+        let mut report = updated_user.validation_report();
+        self.password_policy
+            .check(&mut report, updated_user.password());
+        report.into_result()?;
 
+        //Searching for target user to update
+        let Some(mut user) = self.store.find_user_by_id(target_id).await? else {
+            return Err(AppError::NotFound("User not found".to_string()));
+        };
+        user.update(&updated_user.with_normalized_email()).await?;
+
+        //Fails with AppError::Conflict when the new email belongs to someone else
+        self.store.update_user(&user).await?;
+        tracing::info!(user_id = target_id, "user updated");
         Ok(())
     }
     pub async fn print_user_count(&self) -> usize {
-        let users = self.users.lock().await;
-        users.len()
-    }
-    // pub async fn get_all_users(&self) -> Vec<User> {
-    //     println!("->> HANDLER - get_all_users");
-    //     let users = self.users.lock().await;
-    //     users.clone()
-    // }
-
-    pub async fn print_users(&self) {
-        println!("->> HANDLER - print_users");
-        let users = self.users.lock().await;
-        for user in users.iter() {
-            println!("{}", user)
-        }
+        self.store.user_count().await.unwrap_or(0)
     }
-    pub async fn find_user(&self, login: LoginInfo) -> Result<usize, String> {
-        println!("->> HANDLER - find_user");
 
-        let users = self.users.lock().await;
+    /// Checks the login against the stored users and returns the matching user's id.
+    /// Unknown emails and wrong passwords give the same `Unauthorized` error.
+    pub async fn find_user(&self, login: LoginInfo) -> Result<usize, AppError> {
+        let result = self.check_login(login).await;
 
-        if let Some(user) = users.iter().find(|u| u.get_base().match_credentials(&login)) {
-            println!("User {} is valid.", user);
-            return Ok(user.user_id());
-        } else {
-            return Err(format!("->> Error - User not found."));
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            match &result {
+                Ok(_) => metrics.count_login(true),
+                Err(AppError::Unauthorized(_)) => metrics.count_login(false),
+                Err(_) => {}
+            }
         }
+        result
     }
-    pub async fn get_user_id_from_session (&self, target_session: &str) -> Result<usize, String> {
-        let sessions = self.sessions.lock().await;
+    /// `find_user` behind the login limits, what the login handler calls.
+    /// A blocked account or address gets `TooManyRequests` before the password is checked,
+    /// failures count against both and a success clears the account's counter.
+    pub async fn attempt_login(
+        &self,
+        login: LoginInfo,
+        client: Option<IpAddr>,
+    ) -> Result<usize, AppError> {
+        let now = SystemTime::now();
+        let keys = self.login_limits.keys(login.email(), client);
 
-        let session = sessions.iter().find(|sess| sess.session_id() == target_session);
-        let target_user_id = match session {
-            Some(session) => session.user_id(),
-            None => return Err("Session is invalid".to_string()),
+        //Read then written back, parallel failures may count once, close enough for a limit
+        let mut counters = Vec::with_capacity(keys.len());
+        for key in keys {
+            let attempts = self.store.find_login_attempts(&key.hash()).await?;
+            let attempts = attempts.unwrap_or_else(|| LoginAttempts::new(&key));
+            if let Some(wait) = attempts.retry_after(now) {
+                tracing::warn!(?wait, "login refused, too many failures");
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &self.metrics {
+                    metrics.count_throttled_login();
+                }
+                return Err(AppError::TooManyRequests(wait));
+            }
+            counters.push((key, attempts));
+        }
+
+        let result = self.find_user(login).await;
+        match &result {
+            Ok(_) => {
+                //The address keeps its count, one good account must not clear it
+                let (_, account) = &counters[0];
+                self.store.delete_login_attempts(account.key_hash()).await?;
+            }
+            Err(AppError::Unauthorized(_)) => {
+                for (key, mut attempts) in counters {
+                    self.login_limits.record_failure(&mut attempts, &key, now);
+                    if attempts.retry_after(now).is_some() {
+                        tracing::warn!(failures = attempts.failures(), "login blocked");
+                    }
+                    self.store.save_login_attempts(&attempts).await?;
+                }
+            }
+            Err(_) => {}
+        }
+        result
+    }
+    async fn check_login(&self, login: LoginInfo) -> Result<usize, AppError> {
+        //Same answer for every email, the length alone says nothing about the accounts
+        if exceeds_length_ceiling(login.password()) {
+            return Err(invalid_credentials());
+        }
+        let Some(mut user) = self.store.find_user_by_email(login.email()).await? else {
+            //Still pay for a hash so unknown emails are not faster to reject
+            spawn_verify_dummy_password(login.password()).await;
+            return Err(invalid_credentials());
         };
 
-        Ok(*target_user_id)
+        match user.verify_credentials(&login).await {
+            PasswordCheck::Invalid => Err(invalid_credentials()),
+            PasswordCheck::Valid => {
+                Span::current().record("user_id", user.user_id());
+                tracing::info!("login accepted");
+                Ok(user.user_id())
+            }
+            PasswordCheck::ValidNeedsRehash(new_hash) => {
+                Span::current().record("user_id", user.user_id());
+                tracing::info!("login accepted, upgrading the password hash");
+                user.set_password_hash(new_hash);
+                if let Err(err) = self.store.update_user(&user).await {
+                    tracing::error!(error = %err, "couldn't store the rehashed password");
+                }
+                Ok(user.user_id())
+            }
+        }
     }
-    pub async fn get_user_profile_from_session_id (&self, target_session: &str) -> Result<UserProfile, String>{
-        let sessions = self.sessions.lock().await;
+    /// Resolves a session token to its user with a single store lookup.
+    /// Unknown and expired sessions are `Unauthorized`, expired ones are dropped on sight
+    /// and live ones get their idle timer renewed.
+    pub async fn authenticate(&self, session_token: &str) -> Result<AuthenticatedUser, AppError> {
+        //Sessions are keyed by the SHA-256 of the token: lookups are a single hash probe
+        //and never compare attacker supplied bytes against stored tokens
+        let token_hash = Session::hash_token(session_token);
+        let now = SystemTime::now();
 
-        let session = sessions.iter().find(|sess| sess.session_id() == target_session);
-        
-        //I will do it here again to avoid dead locks
-        let target_user_id = match session {
-            Some(sess) => sess.user_id(),
-            None => return Err("Session is invalid".to_string()),
+        let Some((mut session, user)) = self.store.find_session_with_user(&token_hash).await?
+        else {
+            return Err(AppError::Unauthorized("Session is invalid".to_string()));
         };
 
-        //Select query from the database later on
-        let users = self.users.lock().await;
-        let user = users.iter().find(|stored| stored.user_id() == *target_user_id);
-        let user_profile = match user {
-            Some(u) => u.get_user_profile(),
-            None => return Err("User not found".to_string()),
-        };
+        if session.is_expired(&self.session_config, now) {
+            self.store.delete_session(&token_hash).await?;
+            return Err(AppError::Unauthorized("Session has expired".to_string()));
+        }
 
-        Ok(user_profile)
+        session.touch(now);
+        self.store.update_session(&session).await?;
+        //Fills the field of the request span, see middleware::RequestLog
+        Span::current().record("user_id", user.user_id());
+        Ok(AuthenticatedUser::new(user, session_token))
+    }
+    pub async fn get_user_id_from_session(&self, target_session: &str) -> Result<usize, AppError> {
+        Ok(self.authenticate(target_session).await?.user_id())
+    }
+    pub async fn get_user_profile_from_session_id(
+        &self,
+        target_session: &str,
+    ) -> Result<UserProfile, AppError> {
+        Ok(self.authenticate(target_session).await?.profile())
     }
     ///////////////////////////////////////////////////////////////////////
     pub async fn is_session_valid(&self, target_session: &str) -> bool {
-        let sessions = self.sessions.lock().await;
-        match sessions.iter().find(|sess| sess.session_id() == target_session) {
-            Some(_session) => return true,
-            None => return false,
-        }
+        self.authenticate(target_session).await.is_ok()
     }
-    pub async fn add_session(&self, user_id: usize) -> Result<Session, String> {
-        println!("->> HANDLER - add_session");
+    /// Creates a session for `user_id` and returns the raw token for the cookie.
+    /// Only the token's hash is kept in the store.
+    pub async fn add_session(&self, user_id: usize) -> Result<String, AppError> {
+        let (new_session, token) = Session::new(user_id)?;
 
-        let mut sessions = self.sessions.lock().await;
-        let sessions_len = sessions.len();
+        self.store.insert_session(&new_session).await?;
+        tracing::debug!(user_id, "session created");
+        Ok(token)
+    }
+    pub async fn delete_session(&mut self, target_session: &str) {
+        let token_hash = Session::hash_token(target_session);
+        if let Err(err) = self.store.delete_session(&token_hash).await {
+            tracing::error!(error = %err, "couldn't delete the session");
+        }
+    }
+    pub async fn print_session_count(&self) -> usize {
+        self.store.session_count().await.unwrap_or(0)
+    }
+    /// Removes every expired session and returns how many were dropped.
+    pub async fn purge_expired_sessions(&self) -> usize {
+        let now = SystemTime::now();
 
-        let new_session = match Session::new(sessions_len.to_string(), user_id) {
-            Ok(session) => session,
-            Err(err_msg) => return Err(err_msg),
-        };
-        sessions.push(new_session.clone());
-        Ok(new_session)
-    }
-    pub async fn print_sessions(&self) {
-        println!("->> HANDLER - print_sessions");
-        let sessions = self.sessions.lock().await;
-        for ses in sessions.iter() {
-            println!("{}", ses);
+        match self
+            .store
+            .delete_expired_sessions(&self.session_config, now)
+            .await
+        {
+            Ok(purged) => purged,
+            Err(err) => {
+                tracing::error!(error = %err, "couldn't purge expired sessions");
+                0
+            }
         }
     }
-    pub async fn delete_session(&mut self, target_session: &str) {
-        println!("->> HANDLER - delete_session");
-        let mut sessions = self.sessions.lock().await;
+    /// Removes the login counters that are forgotten and no longer blocking.
+    pub async fn purge_stale_login_attempts(&self) -> usize {
+        let now = SystemTime::now();
+        let failed_before = now
+            .checked_sub(self.login_limits.window())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
 
-        sessions.retain(|s| s.session_id() != target_session);
+        match self
+            .store
+            .delete_stale_login_attempts(failed_before, now)
+            .await
+        {
+            Ok(purged) => purged,
+            Err(err) => {
+                tracing::error!(error = %err, "couldn't purge stale login attempts");
+                0
+            }
+        }
     }
-    pub async fn print_session_count(&self) -> usize {
-        let sessions = self.sessions.lock().await;
-        sessions.len()
+    /// Closes the store, run once after the server stopped.
+    pub async fn close(&self) {
+        self.store.close().await;
+    }
+    /// `close`, but stops waiting for connections still in use after `limit`.
+    /// False when it gave up, the store refuses new work either way.
+    pub async fn close_within(&self, limit: Duration) -> bool {
+        tokio::time::timeout(limit, self.close()).await.is_ok()
+    }
+
+    /// Starts a background task that purges expired sessions
+    /// and stale login counters every `interval`.
+    pub fn spawn_session_reaper(&self, interval: Duration) -> JoinHandle<()> {
+        let app_state = self.clone();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let purged = app_state.purge_expired_sessions().await;
+                if purged > 0 {
+                    tracing::info!(purged, "purged expired sessions");
+                }
+                let purged = app_state.purge_stale_login_attempts().await;
+                if purged > 0 {
+                    tracing::debug!(purged, "purged stale login attempts");
+                }
+            }
+        })
     }
 }
+
+//Same answer for unknown emails and wrong passwords, so accounts can't be probed
+fn invalid_credentials() -> AppError {
+    AppError::Unauthorized("Invalid email or password".to_string())
+}
@@ -0,0 +1,217 @@
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "embed")]
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+#[cfg(feature = "embed")]
+static EMBEDDED_PAGES: include_dir::Dir<'static> =
+    include_dir::include_dir!("$CARGO_MANIFEST_DIR/pages");
+
+/// Where pages and static files are read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Assets {
+    /// A directory read at runtime.
+    Disk(PathBuf),
+    /// `pages/` as it was at compile time, under the given sub directory.
+    /// Only with the `embed` feature.
+    #[cfg(feature = "embed")]
+    Embedded(PathBuf),
+}
+
+/// The embedded copy when built with `embed`, `./pages` otherwise.
+impl Default for Assets {
+    #[cfg(feature = "embed")]
+    fn default() -> Self {
+        Assets::Embedded(PathBuf::new())
+    }
+    #[cfg(not(feature = "embed"))]
+    fn default() -> Self {
+        Assets::Disk(PathBuf::from(crate::structs::Pages::DIR))
+    }
+}
+
+impl Assets {
+    /// Reads from `pages_dir` when one is configured, otherwise the default.
+    /// Whether edits are picked up is up to `Templates`, see `Templates::from_dir`.
+    pub fn select(pages_dir: Option<PathBuf>) -> Self {
+        match pages_dir {
+            Some(dir) => Assets::Disk(dir),
+            None => Assets::default(),
+        }
+    }
+    pub fn is_embedded(&self) -> bool {
+        !matches!(self, Assets::Disk(_))
+    }
+    /// The same source rooted at `sub`, e.g. `static`.
+    pub fn join(&self, sub: &str) -> Self {
+        match self {
+            Assets::Disk(dir) => Assets::Disk(dir.join(sub)),
+            #[cfg(feature = "embed")]
+            Assets::Embedded(prefix) => Assets::Embedded(prefix.join(sub)),
+        }
+    }
+    /// Text of a template such as `partials/flash.html`.
+    /// Names going up with `..` are refused.
+    pub fn read_to_string(&self, name: &str) -> Option<String> {
+        if name
+            .split(['/', '\\'])
+            .any(|segment| segment.is_empty() || segment == "..")
+        {
+            return None;
+        }
+        match self {
+            Assets::Disk(dir) => std::fs::read_to_string(dir.join(name)).ok(),
+            #[cfg(feature = "embed")]
+            Assets::Embedded(prefix) => EMBEDDED_PAGES
+                .get_file(prefix.join(name))
+                .and_then(|file| file.contents_utf8())
+                .map(String::from),
+        }
+    }
+    /// A regular file at the relative `path`. On disk it must really
+    /// live under the directory, so symlinks pointing out are not followed.
+    pub async fn find(&self, path: &Path) -> Option<AssetFile> {
+        match self {
+            Assets::Disk(dir) => {
+                let root = fs::canonicalize(dir).await.ok()?;
+                let real = fs::canonicalize(dir.join(path)).await.ok()?;
+                if !real.starts_with(&root) {
+                    return None;
+                }
+                let meta = fs::metadata(&real).await.ok()?;
+                if !meta.is_file() {
+                    return None;
+                }
+                Some(AssetFile {
+                    len: meta.len(),
+                    modified: meta.modified().ok(),
+                    content: Content::Disk(real),
+                })
+            }
+            #[cfg(feature = "embed")]
+            Assets::Embedded(prefix) => {
+                let file = EMBEDDED_PAGES.get_file(prefix.join(path))?;
+                Some(AssetFile {
+                    len: file.contents().len() as u64,
+                    modified: None,
+                    content: Content::Embedded(file.contents()),
+                })
+            }
+        }
+    }
+    /// Newest modification time of anything under a `Disk` directory,
+    /// `None` for embedded assets since they never change.
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        match self {
+            Assets::Disk(dir) => newest_in(dir),
+            #[cfg(feature = "embed")]
+            Assets::Embedded(_) => None,
+        }
+    }
+}
+
+fn newest_in(dir: &Path) -> Option<SystemTime> {
+    let mut newest = std::fs::metadata(dir).and_then(|meta| meta.modified()).ok();
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let modified = match meta.is_dir() {
+            true => newest_in(&entry.path()),
+            false => meta.modified().ok(),
+        };
+        newest = newest.max(modified);
+    }
+    newest
+}
+
+impl Display for Assets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assets::Disk(dir) => write!(f, "{}", dir.display()),
+            #[cfg(feature = "embed")]
+            Assets::Embedded(prefix) => {
+                write!(f, "pages/{} built into the binary", prefix.display())
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+enum Content {
+    Disk(PathBuf),
+    #[cfg(feature = "embed")]
+    Embedded(&'static [u8]),
+}
+
+/// A file found by `Assets::find`.
+pub struct AssetFile {
+    len: u64,
+    modified: Option<SystemTime>,
+    content: Content,
+}
+
+impl AssetFile {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// `None` for embedded files, they have no useful timestamp.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+    /// Changes whenever the content does, the base of the `ETag`.
+    pub fn fingerprint(&self) -> String {
+        match &self.content {
+            Content::Disk(_) => {
+                let modified = self
+                    .modified
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|dur| dur.as_nanos())
+                    .unwrap_or(0);
+                format!("{:x}-{:x}", self.len, modified)
+            }
+            #[cfg(feature = "embed")]
+            Content::Embedded(bytes) => {
+                let digest = Sha256::digest(bytes);
+                digest[..8]
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect()
+            }
+        }
+    }
+    pub async fn read(&self) -> std::io::Result<Cow<'static, [u8]>> {
+        match &self.content {
+            Content::Disk(path) => Ok(Cow::Owned(fs::read(path).await?)),
+            #[cfg(feature = "embed")]
+            Content::Embedded(bytes) => Ok(Cow::Borrowed(bytes)),
+        }
+    }
+    /// Bytes `start..=end`, both within the file.
+    pub async fn read_range(&self, start: u64, end: u64) -> std::io::Result<Cow<'static, [u8]>> {
+        match &self.content {
+            Content::Disk(path) => {
+                let mut file = File::open(path).await?;
+                file.seek(SeekFrom::Start(start)).await?;
+                let mut content = Vec::with_capacity((end - start + 1) as usize);
+                file.take(end - start + 1).read_to_end(&mut content).await?;
+                Ok(Cow::Owned(content))
+            }
+            #[cfg(feature = "embed")]
+            Content::Embedded(bytes) => Ok(Cow::Borrowed(&bytes[start as usize..=end as usize])),
+        }
+    }
+}
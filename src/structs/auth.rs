@@ -0,0 +1,66 @@
+use hyper::{Body, Request, header};
+
+use crate::{
+    structs::{
+        AppError,
+        app_state::AppState,
+        user::{StoredUser, UserProfile},
+    },
+    utils::extract_session_id_from_header,
+};
+
+/// The logged in user behind a request, resolved once from the session cookie.
+//No Debug, the stored user carries the password hash
+#[derive(Clone)]
+pub struct AuthenticatedUser {
+    user: StoredUser,
+    session_token: String,
+}
+
+impl AuthenticatedUser {
+    pub(crate) fn new(user: StoredUser, session_token: &str) -> Self {
+        Self {
+            user,
+            session_token: session_token.to_string(),
+        }
+    }
+
+    /// Reads the session cookie and loads its user, `Unauthorized` without a live session.
+    pub async fn from_request(
+        request: &Request<Body>,
+        app_state: &AppState,
+    ) -> Result<Self, AppError> {
+        let session_token =
+            extract_session_id_from_header(request.headers(), app_state.cookie_config())?;
+        app_state.authenticate(&session_token).await
+    }
+    /// The user put in the request extensions by the `RequireAuth` middleware.
+    pub fn of(request: &Request<Body>) -> Result<&AuthenticatedUser, AppError> {
+        request
+            .extensions()
+            .get::<AuthenticatedUser>()
+            .ok_or_else(|| AppError::Internal("Route is missing RequireAuth".to_string()))
+    }
+
+    pub fn user(&self) -> &StoredUser {
+        &self.user
+    }
+    pub fn user_id(&self) -> usize {
+        self.user.user_id()
+    }
+    pub fn profile(&self) -> UserProfile {
+        self.user.get_user_profile()
+    }
+    pub fn session_token(&self) -> &str {
+        &self.session_token
+    }
+}
+
+/// Browsers navigating to a page ask for HTML, `fetch` and API clients don't.
+pub fn wants_html(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
@@ -1,5 +1,5 @@
 pub struct Constants;
 
 impl Constants {
-    pub const SESSION_ID_KEY: &str = "session_id=";
+    pub const SESSION_COOKIE: &str = "session_id";
 }
@@ -1,21 +1,161 @@
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
+use hyper::{Body, Method, Response, StatusCode, header};
+use serde_json::json;
+
+use crate::{
+    router::allow_header,
+    structs::{traits::IntoResponse, validation::ValidationReport},
+};
+
+#[derive(Debug)]
 pub enum AppError {
-    SqlxError(sqlx::Error),
-    UserError(String),
+    /// The request itself is malformed, e.g. a body that isn't the expected JSON.
+    BadRequest(String),
+    /// Some fields of the submitted data are not acceptable, all of them are listed.
+    Validation(ValidationReport),
+    /// Missing or wrong credentials, or no live session.
+    Unauthorized(String),
+    /// Known who it is, but the request isn't allowed, e.g. a missing CSRF token.
+    Forbidden(String),
+    NotFound(String),
+    /// The path exists but not for this method, carries the methods it does take.
+    MethodNotAllowed(Vec<Method>),
+    /// The request clashes with existing data, e.g. an email that is already registered.
+    Conflict(String),
+    /// The body is larger than the endpoint reads, carries the limit in bytes.
+    PayloadTooLarge(usize),
+    /// Too many failed logins, the client may try again after the duration.
+    TooManyRequests(Duration),
+    /// The store failed. The cause is logged but never sent to the client.
+    Storage(sqlx::Error),
+    /// Anything else that is on our side, e.g. the hasher or the RNG failing.
+    Internal(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Storage(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    //What the client gets to read, server side failures stay vague
+    fn detail(&self) -> String {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg) => msg.clone(),
+            AppError::Validation(_) => "One or more fields are invalid".to_string(),
+            AppError::MethodNotAllowed(allowed) => {
+                format!("Allowed methods: {}", allow_header(allowed))
+            }
+            AppError::PayloadTooLarge(limit) => {
+                format!("The request body may be at most {} bytes", limit)
+            }
+            AppError::TooManyRequests(_) => "Too many failed logins, try again later".to_string(),
+            AppError::Storage(_) | AppError::Internal(_) => {
+                "Something went wrong on our side".to_string()
+            }
+        }
+    }
 }
 
 impl Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            AppError::SqlxError(e) => write!(f, "Database error: {}", e),
-            AppError::UserError(msg) => write!(f, "User error: {}", msg),
+            AppError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            AppError::Validation(report) => write!(f, "Validation failed: {}", report),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            AppError::MethodNotAllowed(allowed) => {
+                write!(f, "Method not allowed, use {}", allow_header(allowed))
+            }
+            AppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            AppError::PayloadTooLarge(limit) => write!(f, "Body over {} bytes", limit),
+            AppError::TooManyRequests(wait) => write!(f, "Too many requests, retry in {:?}", wait),
+            AppError::Storage(e) => write!(f, "Database error: {}", e),
+            AppError::Internal(msg) => write!(f, "Internal error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Storage(e) => Some(e),
+            _ => None,
         }
     }
 }
 
 impl From<sqlx::Error> for AppError {
     fn from(value: sqlx::Error) -> Self {
-        AppError::SqlxError(value)
+        if let sqlx::Error::Database(db_err) = &value
+            && db_err.is_unique_violation()
+            && violates_email_index(db_err.as_ref())
+        {
+            return AppError::Conflict("Email is already registered".to_string());
+        }
+        AppError::Storage(value)
     }
 }
+
+//SQLite names neither the index nor the constraint,
+//its message ends in the columns: "UNIQUE constraint failed: users.email"
+fn violates_email_index(db_err: &dyn sqlx::error::DatabaseError) -> bool {
+    const INDEX: &str = "users_email_unique";
+    db_err.constraint() == Some(INDEX)
+        || db_err.message().contains(INDEX)
+        || db_err.message().ends_with(": users.email")
+}
+
+/// Answers with an RFC 7807 problem document,
+/// validation errors also list every failing field under `errors`.
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response<Body> {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!(status = status.as_u16(), error = %self, "request failed");
+        }
+
+        let mut problem = json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_u16(),
+            "detail": self.detail(),
+        });
+        if let AppError::Validation(report) = &self {
+            problem["errors"] = json!(report);
+        }
+
+        let mut response = Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/problem+json");
+        if let AppError::MethodNotAllowed(allowed) = &self {
+            response = response.header(header::ALLOW, allow_header(allowed));
+        }
+        if let AppError::TooManyRequests(wait) = &self {
+            response = response.header(header::RETRY_AFTER, retry_after_secs(*wait));
+        }
+
+        response.body(Body::from(problem.to_string())).unwrap()
+    }
+}
+
+//Whole seconds, rounded up so the client doesn't come back a moment too early
+fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
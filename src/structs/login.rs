@@ -1,20 +1,25 @@
+use std::fmt::Debug;
+
 use serde::Deserialize;
 
 use crate::structs::{
+    AppError,
     traits::Extractable,
-    user::{validate_email, validate_password},
+    user::{check_email, check_password},
+    validation::ValidationReport,
 };
 
-#[derive(Deserialize, PartialEq, Eq, Debug)]
+#[derive(Deserialize, PartialEq, Eq)]
 pub struct LoginInfo {
     email: String,
     password: String,
 }
 impl LoginInfo {
-    pub fn new(email: &str, password: &str) -> Result<Self, String> {
-        if !validate_email(email) || !validate_password(password) {
-            return Err(String::from("Couldn't create LoginInfo"));
-        }
+    pub fn new(email: &str, password: &str) -> Result<Self, AppError> {
+        let mut report = ValidationReport::new();
+        check_email(&mut report, email);
+        check_password(&mut report, password);
+        report.into_result()?;
 
         Ok(Self {
             email: email.to_string(),
@@ -35,4 +40,14 @@ impl LoginInfo {
     }
 }
 
+//Hand written so the plaintext password never ends up in debug output
+impl Debug for LoginInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginInfo")
+            .field("email", &self.email)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
 impl Extractable for LoginInfo {}
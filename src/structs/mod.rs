@@ -1,12 +1,19 @@
 pub mod app_state;
+pub mod assets;
+pub mod auth;
 pub mod constants;
 pub mod error;
 pub mod login;
 pub mod pages;
+pub mod password;
 pub mod routes;
 pub mod session;
+pub mod store;
+pub mod templates;
+pub mod throttle;
 pub mod traits;
 pub mod user;
+pub mod validation;
 
 pub use constants::Constants;
 pub use error::AppError;
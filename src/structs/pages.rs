@@ -1,9 +1,15 @@
 pub struct Pages;
 
 impl Pages {
+    pub const DIR: &str = "pages";
+    /// Inside `DIR`, served under `/static/`.
+    pub const STATIC_DIR: &str = "static";
+
     pub const HOME: &str = "home.html";
     pub const LOGIN: &str = "login.html";
     pub const REGISTER: &str = "register.html";
     pub const PROFILE: &str = "profile.html";
-    pub const CSS_FILE: &str = "loginPageStyle.css";
+
+    /// Every page template, checked at startup.
+    pub const ALL: [&str; 4] = [Pages::HOME, Pages::LOGIN, Pages::REGISTER, Pages::PROFILE];
 }
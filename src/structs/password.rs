@@ -0,0 +1,231 @@
+use std::sync::LazyLock;
+
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
+};
+
+use serde::Deserialize;
+
+use crate::structs::{AppError, user::ValidationCodes, validation::ValidationReport};
+
+////////////////////////////////////////////////////////////////////
+struct PasswordConsts {}
+impl PasswordConsts {
+    //OWASP recommended minimum for Argon2id: 19 MiB, 2 iterations, 1 lane
+    const MEMORY_COST_KIB: u32 = 19 * 1024;
+    const TIME_COST: u32 = 2;
+    const PARALLELISM: u32 = 1;
+
+    //Below this the built-in user validation already refuses the password
+    const MIN_LENGTH_FLOOR: usize = 8;
+    //Argon2 hashes whatever it gets, keep huge inputs out
+    const MAX_LENGTH_CEILING: usize = 1024;
+}
+
+//Hash used to burn the same amount of time when the email is unknown,
+//so the response time does not reveal which accounts exist
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("dummy-password-for-timing").unwrap_or_default());
+
+////////////////////////////////////////////////////////////////////
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordCheck {
+    Invalid,
+    Valid,
+    /// The password matched, but the stored hash uses outdated parameters.
+    /// Carries a fresh hash that should replace the stored one.
+    ValidNeedsRehash(String),
+}
+
+impl PasswordCheck {
+    pub fn is_valid(&self) -> bool {
+        !matches!(self, PasswordCheck::Invalid)
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+/// Rules for new passwords, the `[password]` section of the config.
+/// Only checked on registration and profile updates, never on login,
+/// so tightening it doesn't lock anyone out.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_digit: bool,
+    pub require_letter: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: PasswordConsts::MIN_LENGTH_FLOOR,
+            max_length: 128,
+            require_digit: false,
+            require_letter: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Adds the first rule `password` breaks to `report`,
+    /// unless the password field already failed.
+    pub fn check(&self, report: &mut ValidationReport, password: &str) {
+        if report.has_field("password") {
+            return;
+        }
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            report.add(
+                "password",
+                ValidationCodes::TOO_SHORT,
+                format!(
+                    "Password must be at least {} characters long.",
+                    self.min_length
+                ),
+            );
+        } else if length > self.max_length {
+            report.add(
+                "password",
+                ValidationCodes::TOO_LONG,
+                format!(
+                    "Password must be at most {} characters long.",
+                    self.max_length
+                ),
+            );
+        } else if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            report.add(
+                "password",
+                ValidationCodes::WEAK,
+                "Password must contain a digit.".to_string(),
+            );
+        } else if self.require_letter && !password.chars().any(char::is_alphabetic) {
+            report.add(
+                "password",
+                ValidationCodes::WEAK,
+                "Password must contain a letter.".to_string(),
+            );
+        }
+    }
+    /// What is wrong with the policy itself, empty when it is usable.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.min_length < PasswordConsts::MIN_LENGTH_FLOOR {
+            problems.push(format!(
+                "password.min_length must be at least {}",
+                PasswordConsts::MIN_LENGTH_FLOOR
+            ));
+        }
+        if self.max_length < self.min_length {
+            problems.push("password.max_length must not be below password.min_length".to_string());
+        }
+        if self.max_length > PasswordConsts::MAX_LENGTH_CEILING {
+            problems.push(format!(
+                "password.max_length must be at most {}",
+                PasswordConsts::MAX_LENGTH_CEILING
+            ));
+        }
+        problems
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+fn argon2() -> Argon2<'static> {
+    let params = Params::new(
+        PasswordConsts::MEMORY_COST_KIB,
+        PasswordConsts::TIME_COST,
+        PasswordConsts::PARALLELISM,
+        None,
+    )
+    .expect("Argon2 parameters are valid");
+
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+/// Hashes a plaintext password into a PHC string (`$argon2id$v=19$m=...`).
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    argon2()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| AppError::Internal(format!("Couldn't hash password {}", err)))
+}
+
+/// Verifies a plaintext password against a PHC string in constant time.
+pub fn verify_password(password: &str, phc_hash: &str) -> PasswordCheck {
+    let Ok(parsed) = PasswordHash::new(phc_hash) else {
+        return PasswordCheck::Invalid;
+    };
+
+    if argon2()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return PasswordCheck::Invalid;
+    }
+
+    if !needs_rehash(&parsed) {
+        return PasswordCheck::Valid;
+    }
+
+    match hash_password(password) {
+        Ok(new_hash) => PasswordCheck::ValidNeedsRehash(new_hash),
+        Err(_) => PasswordCheck::Valid,
+    }
+}
+
+/// Longer than any `PasswordPolicy` may allow, so no account can have it.
+/// Login refuses these before hashing, Argon2 would take whatever size it is given.
+pub fn exceeds_length_ceiling(password: &str) -> bool {
+    password.chars().count() > PasswordConsts::MAX_LENGTH_CEILING
+}
+
+/// Runs a verification against a throwaway hash. Used when no account matches
+/// so that a miss costs as much time as a wrong password.
+pub fn verify_dummy_password(password: &str) {
+    let _ = verify_password(password, &DUMMY_HASH);
+}
+
+//Argon2 keeps a core busy for tens of milliseconds, run inline it would hold up
+//every other request on the same worker. These run it on the blocking pool instead.
+
+/// `hash_password` off the async workers.
+pub async fn spawn_hash_password(password: &str) -> Result<String, AppError> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .map_err(|err| AppError::Internal(format!("Password hashing task failed {}", err)))?
+}
+
+/// `verify_password` off the async workers, `Invalid` if the task fails.
+pub async fn spawn_verify_password(password: &str, phc_hash: &str) -> PasswordCheck {
+    let (password, phc_hash) = (password.to_string(), phc_hash.to_string());
+    tokio::task::spawn_blocking(move || verify_password(&password, &phc_hash))
+        .await
+        .unwrap_or(PasswordCheck::Invalid)
+}
+
+/// `verify_dummy_password` off the async workers.
+pub async fn spawn_verify_dummy_password(password: &str) {
+    let password = password.to_string();
+    let _ = tokio::task::spawn_blocking(move || verify_dummy_password(&password)).await;
+}
+
+fn needs_rehash(hash: &PasswordHash) -> bool {
+    if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(hash) {
+        Ok(params) => {
+            params.m_cost() != PasswordConsts::MEMORY_COST_KIB
+                || params.t_cost() != PasswordConsts::TIME_COST
+                || params.p_cost() != PasswordConsts::PARALLELISM
+        }
+        Err(_) => true,
+    }
+}
@@ -8,7 +8,9 @@ impl Routes {
     pub const PROFILE: &str = "/profile";
     pub const USER_PROFILE: &str = "/profile/user";
     pub const LOGOUT: &str = "/logout";
-    
-    
-    pub const PAGE_CSS_FILE: &str = "/loginPageStyle.css";
+    pub const METRICS: &str = "/metrics";
+    pub const HEALTH: &str = "/healthz";
+    pub const READY: &str = "/readyz";
+
+    pub const STATIC: &str = "/static/{*path}";
 }
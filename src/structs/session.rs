@@ -1,31 +1,145 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
 
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::{RngCore, rngs::OsRng};
+use sha2::{Digest, Sha256};
+
+use crate::structs::AppError;
+
+////////////////////////////////////////////////////////////////////
+struct SessionConsts {}
+impl SessionConsts {
+    //256 bits of entropy, well above the 128 bit minimum
+    const TOKEN_BYTES: usize = 32;
+
+    const DEFAULT_ABSOLUTE_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+    const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+}
+
+/// SHA-256 of a session token. This is the only form kept on the server,
+/// so a leaked session store does not hand out usable cookies.
+pub type SessionTokenHash = [u8; 32];
+
+////////////////////////////////////////////////////////////////////
+/// How long sessions may live.
+/// A session ends when either limit is hit, whichever comes first.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionConfig {
+    //Counted from the login, never extended
+    absolute_lifetime: Duration,
+    //Counted from the last authenticated request
+    idle_timeout: Duration,
+}
+impl SessionConfig {
+    pub fn new(absolute_lifetime: Duration, idle_timeout: Duration) -> Self {
+        Self {
+            absolute_lifetime,
+            idle_timeout,
+        }
+    }
+    pub fn absolute_lifetime(&self) -> Duration {
+        self.absolute_lifetime
+    }
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self::new(
+            SessionConsts::DEFAULT_ABSOLUTE_LIFETIME,
+            SessionConsts::DEFAULT_IDLE_TIMEOUT,
+        )
+    }
+}
+
+////////////////////////////////////////////////////////////////////
 #[derive(Clone)]
 pub struct Session {
-    session_id: String,
+    token_hash: SessionTokenHash,
     user_id: usize,
+    created_at: SystemTime,
+    last_seen: SystemTime,
 }
 impl Session {
-    pub fn new(session: String, user_id: usize) -> Result<Self, String> {
-        if session.is_empty() {
-            return Err(String::from("Invalid email"));
+    /// Mints a new session for `user_id`.
+    /// Returns the session to store and the raw token to hand to the client.
+    pub fn new(user_id: usize) -> Result<(Self, String), AppError> {
+        let mut bytes = [0u8; SessionConsts::TOKEN_BYTES];
+        OsRng.try_fill_bytes(&mut bytes).map_err(|err| {
+            AppError::Internal(format!("Couldn't generate session token {}", err))
+        })?;
+
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        let now = SystemTime::now();
+        let session = Self {
+            token_hash: Self::hash_token(&token),
+            user_id,
+            created_at: now,
+            last_seen: now,
+        };
+
+        Ok((session, token))
+    }
+
+    /// Rebuilds a session from already persisted fields (e.g. a database row).
+    pub fn from_parts(
+        token_hash: SessionTokenHash,
+        user_id: usize,
+        created_at: SystemTime,
+        last_seen: SystemTime,
+    ) -> Self {
+        Self {
+            token_hash,
+            user_id,
+            created_at,
+            last_seen,
         }
-        Ok(Self {
-            session_id: session.clone(),
-            user_id: user_id,
-        })
     }
 
-    pub fn session_id(&self) -> &str {
-        &self.session_id
+    pub fn hash_token(token: &str) -> SessionTokenHash {
+        Sha256::digest(token.as_bytes()).into()
+    }
+
+    pub fn token_hash(&self) -> &SessionTokenHash {
+        &self.token_hash
     }
     pub fn user_id(&self) -> &usize {
         &self.user_id
     }
+    pub fn created_at(&self) -> SystemTime {
+        self.created_at
+    }
+    pub fn last_seen(&self) -> SystemTime {
+        self.last_seen
+    }
+
+    /// The moment the session stops being valid unless it is used again.
+    pub fn expires_at(&self, config: &SessionConfig) -> SystemTime {
+        let absolute_end = self.created_at + config.absolute_lifetime;
+        let idle_end = self.last_seen + config.idle_timeout;
+        absolute_end.min(idle_end)
+    }
+    pub fn is_expired(&self, config: &SessionConfig, now: SystemTime) -> bool {
+        now >= self.expires_at(config)
+    }
+    /// Sliding renewal, pushes the idle deadline forward.
+    pub fn touch(&mut self, now: SystemTime) {
+        self.last_seen = self.last_seen.max(now);
+    }
 }
 
 impl Display for Session {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Session id {} user id {}", self.session_id, self.user_id)
+        //Only a short prefix of the hash, enough to tell sessions apart in logs
+        let prefix: String = self.token_hash[..4]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        write!(f, "Session {}… user id {}", prefix, self.user_id)
     }
 }
@@ -0,0 +1,179 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use async_trait::async_trait;
+use sqlx::AnyPool;
+use tokio::sync::Mutex;
+
+use crate::structs::{
+    AppError,
+    session::{Session, SessionConfig, SessionTokenHash},
+    store::{LoginAttemptStore, SessionStore, Store, UserStore},
+    throttle::{AttemptKeyHash, LoginAttempts},
+    user::{StoredUser, User, normalize_email},
+};
+
+#[derive(Default)]
+struct UserCache {
+    by_id: HashMap<usize, StoredUser>,
+    id_by_email: HashMap<String, usize>,
+}
+
+impl UserCache {
+    fn put(&mut self, user: &StoredUser) {
+        if let Some(old) = self.by_id.insert(user.user_id(), user.clone()) {
+            self.id_by_email.remove(old.email());
+        }
+        self.id_by_email
+            .insert(user.email().to_string(), user.user_id());
+    }
+}
+
+/// Read-through cache for user lookups in front of another store.
+/// Every write goes to the inner store first, so nothing is lost on restart.
+/// Sessions and login counters are not cached, they change on every request.
+///
+/// Only safe while this process is the sole writer of the database.
+pub struct CachedStore<S> {
+    inner: S,
+    users: Mutex<UserCache>,
+}
+
+impl<S: Store> CachedStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            users: Mutex::new(UserCache::default()),
+        }
+    }
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<S: Store> Store for CachedStore<S> {
+    async fn close(&self) {
+        self.inner.close().await;
+    }
+    async fn ping(&self) -> Result<(), AppError> {
+        drop(self.users.lock().await);
+        self.inner.ping().await
+    }
+    fn sql_pool(&self) -> Option<&AnyPool> {
+        self.inner.sql_pool()
+    }
+}
+
+#[async_trait]
+impl<S: Store> UserStore for CachedStore<S> {
+    async fn insert_user(&self, user: &User, password_hash: &str) -> Result<StoredUser, AppError> {
+        let stored = self.inner.insert_user(user, password_hash).await?;
+        self.users.lock().await.put(&stored);
+        Ok(stored)
+    }
+    async fn update_user(&self, user: &StoredUser) -> Result<(), AppError> {
+        self.inner.update_user(user).await?;
+        self.users.lock().await.put(user);
+        Ok(())
+    }
+    async fn find_user_by_id(&self, user_id: usize) -> Result<Option<StoredUser>, AppError> {
+        if let Some(user) = self.users.lock().await.by_id.get(&user_id) {
+            return Ok(Some(user.clone()));
+        }
+
+        let found = self.inner.find_user_by_id(user_id).await?;
+        if let Some(user) = &found {
+            self.users.lock().await.put(user);
+        }
+        Ok(found)
+    }
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<StoredUser>, AppError> {
+        let email = normalize_email(email);
+        {
+            let cache = self.users.lock().await;
+            if let Some(user) = cache
+                .id_by_email
+                .get(&email)
+                .and_then(|id| cache.by_id.get(id))
+            {
+                return Ok(Some(user.clone()));
+            }
+        }
+
+        let found = self.inner.find_user_by_email(&email).await?;
+        if let Some(user) = &found {
+            self.users.lock().await.put(user);
+        }
+        Ok(found)
+    }
+    async fn user_count(&self) -> Result<usize, AppError> {
+        self.inner.user_count().await
+    }
+    async fn all_users(&self) -> Result<Vec<StoredUser>, AppError> {
+        self.inner.all_users().await
+    }
+}
+
+#[async_trait]
+impl<S: Store> SessionStore for CachedStore<S> {
+    async fn insert_session(&self, session: &Session) -> Result<(), AppError> {
+        self.inner.insert_session(session).await
+    }
+    async fn find_session(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<Session>, AppError> {
+        self.inner.find_session(token_hash).await
+    }
+    async fn find_session_with_user(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<(Session, StoredUser)>, AppError> {
+        //Already a single query, the cache would only add a lock
+        self.inner.find_session_with_user(token_hash).await
+    }
+    async fn update_session(&self, session: &Session) -> Result<(), AppError> {
+        self.inner.update_session(session).await
+    }
+    async fn delete_session(&self, token_hash: &SessionTokenHash) -> Result<(), AppError> {
+        self.inner.delete_session(token_hash).await
+    }
+    async fn delete_expired_sessions(
+        &self,
+        config: &SessionConfig,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        self.inner.delete_expired_sessions(config, now).await
+    }
+    async fn session_count(&self) -> Result<usize, AppError> {
+        self.inner.session_count().await
+    }
+    async fn all_sessions(&self) -> Result<Vec<Session>, AppError> {
+        self.inner.all_sessions().await
+    }
+}
+
+#[async_trait]
+impl<S: Store> LoginAttemptStore for CachedStore<S> {
+    async fn find_login_attempts(
+        &self,
+        key_hash: &AttemptKeyHash,
+    ) -> Result<Option<LoginAttempts>, AppError> {
+        self.inner.find_login_attempts(key_hash).await
+    }
+    async fn save_login_attempts(&self, attempts: &LoginAttempts) -> Result<(), AppError> {
+        self.inner.save_login_attempts(attempts).await
+    }
+    async fn delete_login_attempts(&self, key_hash: &AttemptKeyHash) -> Result<(), AppError> {
+        self.inner.delete_login_attempts(key_hash).await
+    }
+    async fn delete_stale_login_attempts(
+        &self,
+        failed_before: SystemTime,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        self.inner
+            .delete_stale_login_attempts(failed_before, now)
+            .await
+    }
+}
@@ -0,0 +1,188 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::structs::{
+    AppError,
+    session::{Session, SessionConfig, SessionTokenHash},
+    store::{LoginAttemptStore, SessionStore, Store, UserStore},
+    throttle::{AttemptKeyHash, LoginAttempts},
+    user::{StoredUser, User, normalize_email},
+};
+
+/// Keeps everything in process memory. Nothing survives a restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    users: Mutex<Vec<StoredUser>>,
+    sessions: Mutex<HashMap<SessionTokenHash, Session>>,
+    login_attempts: Mutex<HashMap<AttemptKeyHash, LoginAttempts>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+//Nothing to flush, it all goes away with the process
+#[async_trait]
+impl Store for MemoryStore {
+    //Every lock can be taken, nothing is stuck holding one
+    async fn ping(&self) -> Result<(), AppError> {
+        drop(self.users.lock().await);
+        drop(self.sessions.lock().await);
+        drop(self.login_attempts.lock().await);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn insert_user(&self, user: &User, password_hash: &str) -> Result<StoredUser, AppError> {
+        let mut users = self.users.lock().await;
+
+        let email = normalize_email(user.email());
+        if users.iter().any(|u| u.email() == email) {
+            return Err(AppError::Conflict(
+                "Email is already registered".to_string(),
+            ));
+        }
+
+        let new_stored_user = StoredUser::from_parts(
+            users.len(),
+            user.first_name(),
+            user.last_name(),
+            &email,
+            password_hash,
+        );
+        users.push(new_stored_user.clone());
+        Ok(new_stored_user)
+    }
+    async fn update_user(&self, user: &StoredUser) -> Result<(), AppError> {
+        let mut users = self.users.lock().await;
+
+        if users
+            .iter()
+            .any(|u| u.email() == user.email() && u.user_id() != user.user_id())
+        {
+            return Err(AppError::Conflict(
+                "Email is already registered".to_string(),
+            ));
+        }
+
+        match users.iter_mut().find(|u| u.user_id() == user.user_id()) {
+            Some(stored) => {
+                *stored = user.clone();
+                Ok(())
+            }
+            None => Err(AppError::NotFound("User not found.".to_string())),
+        }
+    }
+    async fn find_user_by_id(&self, user_id: usize) -> Result<Option<StoredUser>, AppError> {
+        let users = self.users.lock().await;
+        Ok(users.iter().find(|u| u.user_id() == user_id).cloned())
+    }
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<StoredUser>, AppError> {
+        let users = self.users.lock().await;
+        let email = normalize_email(email);
+        Ok(users.iter().find(|u| u.email() == email).cloned())
+    }
+    async fn user_count(&self) -> Result<usize, AppError> {
+        Ok(self.users.lock().await.len())
+    }
+    async fn all_users(&self) -> Result<Vec<StoredUser>, AppError> {
+        Ok(self.users.lock().await.clone())
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn insert_session(&self, session: &Session) -> Result<(), AppError> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(*session.token_hash(), session.clone());
+        Ok(())
+    }
+    async fn find_session(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<Session>, AppError> {
+        let sessions = self.sessions.lock().await;
+        Ok(sessions.get(token_hash).cloned())
+    }
+    async fn find_session_with_user(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<(Session, StoredUser)>, AppError> {
+        let sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get(token_hash) else {
+            return Ok(None);
+        };
+
+        let users = self.users.lock().await;
+        let user = users
+            .iter()
+            .find(|user| user.user_id() == *session.user_id());
+        Ok(user.map(|user| (session.clone(), user.clone())))
+    }
+    async fn update_session(&self, session: &Session) -> Result<(), AppError> {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(stored) = sessions.get_mut(session.token_hash()) {
+            *stored = session.clone();
+        }
+        Ok(())
+    }
+    async fn delete_session(&self, token_hash: &SessionTokenHash) -> Result<(), AppError> {
+        self.sessions.lock().await.remove(token_hash);
+        Ok(())
+    }
+    async fn delete_expired_sessions(
+        &self,
+        config: &SessionConfig,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        let mut sessions = self.sessions.lock().await;
+        let before = sessions.len();
+
+        sessions.retain(|_, sess| !sess.is_expired(config, now));
+        Ok(before - sessions.len())
+    }
+    async fn session_count(&self) -> Result<usize, AppError> {
+        Ok(self.sessions.lock().await.len())
+    }
+    async fn all_sessions(&self) -> Result<Vec<Session>, AppError> {
+        Ok(self.sessions.lock().await.values().cloned().collect())
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for MemoryStore {
+    async fn find_login_attempts(
+        &self,
+        key_hash: &AttemptKeyHash,
+    ) -> Result<Option<LoginAttempts>, AppError> {
+        Ok(self.login_attempts.lock().await.get(key_hash).cloned())
+    }
+    async fn save_login_attempts(&self, attempts: &LoginAttempts) -> Result<(), AppError> {
+        let mut login_attempts = self.login_attempts.lock().await;
+        login_attempts.insert(*attempts.key_hash(), attempts.clone());
+        Ok(())
+    }
+    async fn delete_login_attempts(&self, key_hash: &AttemptKeyHash) -> Result<(), AppError> {
+        self.login_attempts.lock().await.remove(key_hash);
+        Ok(())
+    }
+    async fn delete_stale_login_attempts(
+        &self,
+        failed_before: SystemTime,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        let mut login_attempts = self.login_attempts.lock().await;
+        let before = login_attempts.len();
+
+        login_attempts.retain(|_, attempts| {
+            attempts.last_failure() >= failed_before || attempts.blocked_until() > now
+        });
+        Ok(before - login_attempts.len())
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use sqlx::AnyPool;
+
+use crate::{
+    metrics::Metrics,
+    structs::{
+        AppError,
+        session::{Session, SessionConfig, SessionTokenHash},
+        store::{LoginAttemptStore, SessionStore, Store, UserStore},
+        throttle::{AttemptKeyHash, LoginAttempts},
+        user::{StoredUser, User},
+    },
+};
+
+/// Times every call into another store, reported per operation
+/// as `my_project_store_operation_duration_seconds`.
+pub struct MeteredStore {
+    inner: Arc<dyn Store>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredStore {
+    pub fn new(inner: Arc<dyn Store>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+    pub fn inner(&self) -> &Arc<dyn Store> {
+        &self.inner
+    }
+
+    async fn timed<T>(&self, operation: &'static str, call: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = call.await;
+        self.metrics.observe_store(operation, started.elapsed());
+        result
+    }
+}
+
+#[async_trait]
+impl Store for MeteredStore {
+    async fn close(&self) {
+        self.inner.close().await;
+    }
+    async fn ping(&self) -> Result<(), AppError> {
+        self.timed("ping", self.inner.ping()).await
+    }
+    fn sql_pool(&self) -> Option<&AnyPool> {
+        self.inner.sql_pool()
+    }
+}
+
+#[async_trait]
+impl UserStore for MeteredStore {
+    async fn insert_user(&self, user: &User, password_hash: &str) -> Result<StoredUser, AppError> {
+        self.timed("insert_user", self.inner.insert_user(user, password_hash))
+            .await
+    }
+    async fn update_user(&self, user: &StoredUser) -> Result<(), AppError> {
+        self.timed("update_user", self.inner.update_user(user))
+            .await
+    }
+    async fn find_user_by_id(&self, user_id: usize) -> Result<Option<StoredUser>, AppError> {
+        self.timed("find_user_by_id", self.inner.find_user_by_id(user_id))
+            .await
+    }
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<StoredUser>, AppError> {
+        self.timed("find_user_by_email", self.inner.find_user_by_email(email))
+            .await
+    }
+    async fn user_count(&self) -> Result<usize, AppError> {
+        self.timed("user_count", self.inner.user_count()).await
+    }
+    async fn all_users(&self) -> Result<Vec<StoredUser>, AppError> {
+        self.timed("all_users", self.inner.all_users()).await
+    }
+}
+
+#[async_trait]
+impl SessionStore for MeteredStore {
+    async fn insert_session(&self, session: &Session) -> Result<(), AppError> {
+        self.timed("insert_session", self.inner.insert_session(session))
+            .await
+    }
+    async fn find_session(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<Session>, AppError> {
+        self.timed("find_session", self.inner.find_session(token_hash))
+            .await
+    }
+    async fn find_session_with_user(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<(Session, StoredUser)>, AppError> {
+        self.timed(
+            "find_session_with_user",
+            self.inner.find_session_with_user(token_hash),
+        )
+        .await
+    }
+    async fn update_session(&self, session: &Session) -> Result<(), AppError> {
+        self.timed("update_session", self.inner.update_session(session))
+            .await
+    }
+    async fn delete_session(&self, token_hash: &SessionTokenHash) -> Result<(), AppError> {
+        self.timed("delete_session", self.inner.delete_session(token_hash))
+            .await
+    }
+    async fn delete_expired_sessions(
+        &self,
+        config: &SessionConfig,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        self.timed(
+            "delete_expired_sessions",
+            self.inner.delete_expired_sessions(config, now),
+        )
+        .await
+    }
+    async fn session_count(&self) -> Result<usize, AppError> {
+        self.timed("session_count", self.inner.session_count())
+            .await
+    }
+    async fn all_sessions(&self) -> Result<Vec<Session>, AppError> {
+        self.timed("all_sessions", self.inner.all_sessions()).await
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for MeteredStore {
+    async fn find_login_attempts(
+        &self,
+        key_hash: &AttemptKeyHash,
+    ) -> Result<Option<LoginAttempts>, AppError> {
+        self.timed(
+            "find_login_attempts",
+            self.inner.find_login_attempts(key_hash),
+        )
+        .await
+    }
+    async fn save_login_attempts(&self, attempts: &LoginAttempts) -> Result<(), AppError> {
+        self.timed(
+            "save_login_attempts",
+            self.inner.save_login_attempts(attempts),
+        )
+        .await
+    }
+    async fn delete_login_attempts(&self, key_hash: &AttemptKeyHash) -> Result<(), AppError> {
+        self.timed(
+            "delete_login_attempts",
+            self.inner.delete_login_attempts(key_hash),
+        )
+        .await
+    }
+    async fn delete_stale_login_attempts(
+        &self,
+        failed_before: SystemTime,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        self.timed(
+            "delete_stale_login_attempts",
+            self.inner.delete_stale_login_attempts(failed_before, now),
+        )
+        .await
+    }
+}
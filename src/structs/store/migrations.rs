@@ -0,0 +1,159 @@
+use std::fmt::Display;
+
+use sqlx::{
+    AnyPool,
+    migrate::{Migrate, MigrateError, Migrator},
+};
+
+use crate::structs::store::sql::SqlBackend;
+
+//Compiled into the binary, so a fresh database needs no manual setup.
+//Applied versions are tracked by sqlx in the `_sqlx_migrations` table.
+static MYSQL_MIGRATOR: Migrator = sqlx::migrate!("./migrations/mysql");
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+pub fn migrator(backend: SqlBackend) -> &'static Migrator {
+    match backend {
+        SqlBackend::MySql => &MYSQL_MIGRATOR,
+        SqlBackend::Sqlite => &SQLITE_MIGRATOR,
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+#[derive(Debug)]
+pub enum MigrationError {
+    Migrate(MigrateError),
+    /// The database has migrations applied that this binary doesn't know,
+    /// it was migrated by a newer release.
+    DatabaseAhead {
+        applied: i64,
+        latest_known: i64,
+    },
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Migrate(err) => write!(f, "Migration error: {}", err),
+            MigrationError::DatabaseAhead {
+                applied,
+                latest_known,
+            } => write!(
+                f,
+                "Database schema is at version {} but this binary only knows up to {}, refusing to run",
+                applied, latest_known
+            ),
+        }
+    }
+}
+
+impl From<MigrateError> for MigrationError {
+    fn from(value: MigrateError) -> Self {
+        MigrationError::Migrate(value)
+    }
+}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(value: sqlx::Error) -> Self {
+        MigrationError::Migrate(MigrateError::Execute(value))
+    }
+}
+
+impl From<MigrationError> for sqlx::Error {
+    fn from(value: MigrationError) -> Self {
+        match value {
+            MigrationError::Migrate(err) => sqlx::Error::Migrate(Box::new(err)),
+            ahead => sqlx::Error::Configuration(ahead.to_string().into()),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+#[derive(Debug, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub applied: Vec<i64>,
+    pub pending: Vec<i64>,
+}
+
+impl Display for MigrationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "applied {:?}, pending {:?}", self.applied, self.pending)
+    }
+}
+
+fn known_versions(backend: SqlBackend) -> Vec<i64> {
+    migrator(backend)
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .collect()
+}
+
+async fn applied_versions(pool: &AnyPool) -> Result<Vec<i64>, MigrationError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    let mut applied: Vec<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+    applied.sort_unstable();
+    Ok(applied)
+}
+
+pub async fn status(
+    pool: &AnyPool,
+    backend: SqlBackend,
+) -> Result<MigrationStatus, MigrationError> {
+    let applied = applied_versions(pool).await?;
+    let pending = known_versions(backend)
+        .into_iter()
+        .filter(|version| !applied.contains(version))
+        .collect();
+
+    Ok(MigrationStatus { applied, pending })
+}
+
+/// Fails when the database carries a migration newer than any this binary ships.
+pub async fn ensure_not_ahead(pool: &AnyPool, backend: SqlBackend) -> Result<(), MigrationError> {
+    let latest_known = known_versions(backend).into_iter().max().unwrap_or(0);
+    let applied = applied_versions(pool).await?;
+
+    match applied.last() {
+        Some(&latest_applied) if latest_applied > latest_known => {
+            Err(MigrationError::DatabaseAhead {
+                applied: latest_applied,
+                latest_known,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Applies every pending migration and returns the versions that ran.
+pub async fn migrate_up(pool: &AnyPool, backend: SqlBackend) -> Result<Vec<i64>, MigrationError> {
+    ensure_not_ahead(pool, backend).await?;
+    let before = status(pool, backend).await?;
+
+    migrator(backend).run(pool).await?;
+    Ok(before.pending)
+}
+
+/// Reverts the last `steps` applied migrations and returns the reverted versions.
+pub async fn migrate_down(
+    pool: &AnyPool,
+    backend: SqlBackend,
+    steps: usize,
+) -> Result<Vec<i64>, MigrationError> {
+    ensure_not_ahead(pool, backend).await?;
+    let applied = applied_versions(pool).await?;
+
+    let keep = applied.len().saturating_sub(steps);
+    //undo reverts everything newer than the target version
+    let target = if keep == 0 { 0 } else { applied[keep - 1] };
+
+    migrator(backend).undo(pool, target).await?;
+    Ok(applied[keep..].iter().rev().copied().collect())
+}
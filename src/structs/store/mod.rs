@@ -0,0 +1,101 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use sqlx::AnyPool;
+
+use crate::structs::{
+    AppError,
+    session::{Session, SessionConfig, SessionTokenHash},
+    throttle::{AttemptKeyHash, LoginAttempts},
+    user::{StoredUser, User},
+};
+
+pub mod cached;
+pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metered;
+pub mod migrations;
+pub mod sql;
+
+pub use cached::CachedStore;
+pub use memory::MemoryStore;
+#[cfg(feature = "metrics")]
+pub use metered::MeteredStore;
+pub use sql::SqlStore;
+
+/// Persistence for registered users.
+/// Stores never see plaintext passwords, only the PHC hash.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Saves a new user and returns it with the id assigned by the store.
+    async fn insert_user(&self, user: &User, password_hash: &str) -> Result<StoredUser, AppError>;
+    /// Overwrites every field of an existing user.
+    async fn update_user(&self, user: &StoredUser) -> Result<(), AppError>;
+    async fn find_user_by_id(&self, user_id: usize) -> Result<Option<StoredUser>, AppError>;
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<StoredUser>, AppError>;
+    async fn user_count(&self) -> Result<usize, AppError>;
+    async fn all_users(&self) -> Result<Vec<StoredUser>, AppError>;
+}
+
+/// Persistence for login sessions, keyed by the hash of the session token.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn insert_session(&self, session: &Session) -> Result<(), AppError>;
+    async fn find_session(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<Session>, AppError>;
+    /// The session together with the user it belongs to, in one lookup.
+    /// Used to authenticate every request.
+    async fn find_session_with_user(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<(Session, StoredUser)>, AppError>;
+    /// Persists the session's timestamps after a sliding renewal.
+    async fn update_session(&self, session: &Session) -> Result<(), AppError>;
+    async fn delete_session(&self, token_hash: &SessionTokenHash) -> Result<(), AppError>;
+    /// Removes every session expired at `now` and returns how many were dropped.
+    async fn delete_expired_sessions(
+        &self,
+        config: &SessionConfig,
+        now: SystemTime,
+    ) -> Result<usize, AppError>;
+    async fn session_count(&self) -> Result<usize, AppError>;
+    async fn all_sessions(&self) -> Result<Vec<Session>, AppError>;
+}
+
+/// Persistence for the failed login counters, see `structs::throttle`.
+/// Kept with the users so a lockout survives a restart and holds on every instance.
+#[async_trait]
+pub trait LoginAttemptStore: Send + Sync {
+    async fn find_login_attempts(
+        &self,
+        key_hash: &AttemptKeyHash,
+    ) -> Result<Option<LoginAttempts>, AppError>;
+    /// Inserts the counter or overwrites the one with the same key.
+    async fn save_login_attempts(&self, attempts: &LoginAttempts) -> Result<(), AppError>;
+    async fn delete_login_attempts(&self, key_hash: &AttemptKeyHash) -> Result<(), AppError>;
+    /// Removes every counter whose last failure is before `failed_before`
+    /// and whose block ran out at `now`, returns how many were dropped.
+    async fn delete_stale_login_attempts(
+        &self,
+        failed_before: SystemTime,
+        now: SystemTime,
+    ) -> Result<usize, AppError>;
+}
+
+/// A backend providing users, sessions and login counters, what `AppState` is built on.
+#[async_trait]
+pub trait Store: UserStore + SessionStore + LoginAttemptStore {
+    /// Flushes whatever is pending and releases connections, called once on shutdown.
+    /// Nothing may be stored afterwards.
+    async fn close(&self) {}
+    /// Cheapest round trip that proves the store can answer, for the readiness probe.
+    async fn ping(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+    /// The connection pool behind the store, for its metrics. `None` when there is none.
+    fn sql_pool(&self) -> Option<&AnyPool> {
+        None
+    }
+}
@@ -0,0 +1,402 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use sqlx::{
+    AnyPool,
+    any::{AnyPoolOptions, install_default_drivers},
+};
+
+use crate::structs::{
+    AppError,
+    session::{Session, SessionConfig, SessionTokenHash},
+    store::{LoginAttemptStore, SessionStore, Store, UserStore, migrations},
+    throttle::{AttemptKeyHash, LoginAttempts},
+    user::{StoredUser, User, normalize_email},
+};
+
+type UserRow = (i64, String, String, String, String);
+type SessionRow = (Vec<u8>, i64, i64, i64);
+type SessionUserRow = (Vec<u8>, i64, i64, i64, i64, String, String, String, String);
+type AttemptsRow = (Vec<u8>, i64, i64, i64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SqlBackend {
+    MySql,
+    Sqlite,
+}
+
+impl SqlBackend {
+    pub fn from_url(db_url: &str) -> Option<Self> {
+        if db_url.starts_with("mysql:") {
+            Some(SqlBackend::MySql)
+        } else if db_url.starts_with("sqlite:") {
+            Some(SqlBackend::Sqlite)
+        } else {
+            None
+        }
+    }
+}
+
+/// Stores users and sessions in MySQL or SQLite, picked from the URL scheme
+/// (`mysql://...`, `sqlite://file.db?mode=rwc`, `sqlite::memory:`).
+///
+/// Both backends share the schema in `migrations/`: `users`, `sessions` and `login_attempts`,
+/// with timestamps in milliseconds since the unix epoch.
+/// Pending migrations are applied on connect.
+pub struct SqlStore {
+    pool: AnyPool,
+    backend: SqlBackend,
+}
+
+impl SqlStore {
+    /// Connects and brings the schema up to date.
+    /// Refuses databases migrated by a newer binary.
+    pub async fn connect(db_url: &str) -> Result<Self, sqlx::Error> {
+        let (pool, backend) = open_pool(db_url).await?;
+
+        let applied = migrations::migrate_up(&pool, backend).await?;
+        if !applied.is_empty() {
+            tracing::info!(?applied, "applied migrations");
+        }
+
+        Ok(Self { pool, backend })
+    }
+    pub fn pool(&self) -> &AnyPool {
+        &self.pool
+    }
+    pub fn backend(&self) -> SqlBackend {
+        self.backend
+    }
+}
+
+/// The part of `db_url` safe to show in errors, the rest may hold a password.
+pub fn url_scheme(db_url: &str) -> &str {
+    db_url
+        .split_once("://")
+        .map_or("unknown", |(scheme, _)| scheme)
+}
+
+/// Opens a pool without touching the schema, used by the `migrate` command.
+pub async fn open_pool(db_url: &str) -> Result<(AnyPool, SqlBackend), sqlx::Error> {
+    install_default_drivers();
+
+    let backend = SqlBackend::from_url(db_url).ok_or_else(|| {
+        let message = format!("Unsupported database scheme {}", url_scheme(db_url));
+        sqlx::Error::Configuration(message.into())
+    })?;
+
+    let mut options = AnyPoolOptions::new();
+    if db_url.contains(":memory:") {
+        //Every connection to :memory: is a separate database, keep exactly one alive
+        options = options
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None);
+    }
+    let pool = options.connect(db_url).await?;
+
+    Ok((pool, backend))
+}
+
+////////////////////////////////////////////////////////////////////
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_millis() as i64)
+        .unwrap_or(0)
+}
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn user_from_row((id, first_name, last_name, email, password): UserRow) -> StoredUser {
+    StoredUser::from_parts(id as usize, &first_name, &last_name, &email, &password)
+}
+fn session_from_row(
+    (token_hash, user_id, created_at, last_seen): SessionRow,
+) -> Result<Session, AppError> {
+    let token_hash: SessionTokenHash = token_hash
+        .try_into()
+        .map_err(|_| AppError::Internal("Malformed session hash in DB".to_string()))?;
+
+    Ok(Session::from_parts(
+        token_hash,
+        user_id as usize,
+        from_millis(created_at),
+        from_millis(last_seen),
+    ))
+}
+
+fn attempts_from_row(
+    (key_hash, failures, last_failure, blocked_until): AttemptsRow,
+) -> Result<LoginAttempts, AppError> {
+    let key_hash: AttemptKeyHash = key_hash
+        .try_into()
+        .map_err(|_| AppError::Internal("Malformed login attempt key in DB".to_string()))?;
+
+    Ok(LoginAttempts::from_parts(
+        key_hash,
+        failures.max(0) as u32,
+        from_millis(last_failure),
+        from_millis(blocked_until),
+    ))
+}
+
+////////////////////////////////////////////////////////////////////
+#[async_trait]
+impl Store for SqlStore {
+    //Waits for the queries still running, then closes every connection
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+    async fn ping(&self) -> Result<(), AppError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+    fn sql_pool(&self) -> Option<&AnyPool> {
+        Some(&self.pool)
+    }
+}
+
+#[async_trait]
+impl UserStore for SqlStore {
+    async fn insert_user(&self, user: &User, password_hash: &str) -> Result<StoredUser, AppError> {
+        let insert =
+            "INSERT INTO users (first_name, last_name, email, password) VALUES (?, ?, ?, ?)";
+        //The unique index on email turns duplicates into AppError::Conflict
+        let email = normalize_email(user.email());
+
+        //The sqlite driver doesn't report the last insert id through `Any`
+        let new_id = match self.backend {
+            SqlBackend::Sqlite => {
+                let row: (i64,) = sqlx::query_as(&format!("{} RETURNING id", insert))
+                    .bind(user.first_name())
+                    .bind(user.last_name())
+                    .bind(&email)
+                    .bind(password_hash)
+                    .fetch_one(&self.pool)
+                    .await?;
+                row.0
+            }
+            SqlBackend::MySql => sqlx::query(insert)
+                .bind(user.first_name())
+                .bind(user.last_name())
+                .bind(&email)
+                .bind(password_hash)
+                .execute(&self.pool)
+                .await?
+                .last_insert_id()
+                .unwrap_or_default(),
+        };
+
+        Ok(StoredUser::from_parts(
+            new_id as usize,
+            user.first_name(),
+            user.last_name(),
+            &email,
+            password_hash,
+        ))
+    }
+    async fn update_user(&self, user: &StoredUser) -> Result<(), AppError> {
+        let result = sqlx::query(
+            "UPDATE users SET first_name = ?, last_name = ?, email = ?, password = ? WHERE id = ?",
+        )
+        .bind(user.first_name())
+        .bind(user.last_name())
+        .bind(user.email())
+        .bind(user.password_hash())
+        .bind(user.user_id() as i64)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("User not found.".to_string()));
+        }
+        Ok(())
+    }
+    async fn find_user_by_id(&self, user_id: usize) -> Result<Option<StoredUser>, AppError> {
+        let row: Option<UserRow> = sqlx::query_as(
+            "SELECT id, first_name, last_name, email, password FROM users WHERE id = ?",
+        )
+        .bind(user_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(user_from_row))
+    }
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<StoredUser>, AppError> {
+        let row: Option<UserRow> = sqlx::query_as(
+            "SELECT id, first_name, last_name, email, password FROM users WHERE email = ?",
+        )
+        .bind(normalize_email(email))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(user_from_row))
+    }
+    async fn user_count(&self) -> Result<usize, AppError> {
+        let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0 as usize)
+    }
+    async fn all_users(&self) -> Result<Vec<StoredUser>, AppError> {
+        let rows: Vec<UserRow> =
+            sqlx::query_as("SELECT id, first_name, last_name, email, password FROM users")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows.into_iter().map(user_from_row).collect())
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqlStore {
+    async fn insert_session(&self, session: &Session) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO sessions (token_hash, user_id, created_at, last_seen) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&session.token_hash()[..])
+        .bind(*session.user_id() as i64)
+        .bind(to_millis(session.created_at()))
+        .bind(to_millis(session.last_seen()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    async fn find_session(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<Session>, AppError> {
+        let row: Option<SessionRow> = sqlx::query_as(
+            "SELECT token_hash, user_id, created_at, last_seen FROM sessions WHERE token_hash = ?",
+        )
+        .bind(&token_hash[..])
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(session_from_row).transpose()
+    }
+    async fn find_session_with_user(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<(Session, StoredUser)>, AppError> {
+        let row: Option<SessionUserRow> = sqlx::query_as(
+            "SELECT s.token_hash, s.user_id, s.created_at, s.last_seen, \
+                u.id, u.first_name, u.last_name, u.email, u.password \
+             FROM sessions s JOIN users u ON u.id = s.user_id WHERE s.token_hash = ?",
+        )
+        .bind(&token_hash[..])
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((token_hash, user_id, created_at, last_seen, id, first, last, email, pw)) = row
+        else {
+            return Ok(None);
+        };
+        let session = session_from_row((token_hash, user_id, created_at, last_seen))?;
+        let user = user_from_row((id, first, last, email, pw));
+        Ok(Some((session, user)))
+    }
+    async fn update_session(&self, session: &Session) -> Result<(), AppError> {
+        sqlx::query("UPDATE sessions SET last_seen = ? WHERE token_hash = ?")
+            .bind(to_millis(session.last_seen()))
+            .bind(&session.token_hash()[..])
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn delete_session(&self, token_hash: &SessionTokenHash) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM sessions WHERE token_hash = ?")
+            .bind(&token_hash[..])
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn delete_expired_sessions(
+        &self,
+        config: &SessionConfig,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        //Same rule as Session::is_expired, either limit reached
+        let result = sqlx::query("DELETE FROM sessions WHERE created_at <= ? OR last_seen <= ?")
+            .bind(to_millis(now) - config.absolute_lifetime().as_millis() as i64)
+            .bind(to_millis(now) - config.idle_timeout().as_millis() as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() as usize)
+    }
+    async fn session_count(&self) -> Result<usize, AppError> {
+        let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM sessions")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0 as usize)
+    }
+    async fn all_sessions(&self) -> Result<Vec<Session>, AppError> {
+        let rows: Vec<SessionRow> =
+            sqlx::query_as("SELECT token_hash, user_id, created_at, last_seen FROM sessions")
+                .fetch_all(&self.pool)
+                .await?;
+        rows.into_iter().map(session_from_row).collect()
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for SqlStore {
+    async fn find_login_attempts(
+        &self,
+        key_hash: &AttemptKeyHash,
+    ) -> Result<Option<LoginAttempts>, AppError> {
+        let row: Option<AttemptsRow> = sqlx::query_as(
+            "SELECT key_hash, failures, last_failure, blocked_until \
+             FROM login_attempts WHERE key_hash = ?",
+        )
+        .bind(&key_hash[..])
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(attempts_from_row).transpose()
+    }
+    async fn save_login_attempts(&self, attempts: &LoginAttempts) -> Result<(), AppError> {
+        let upsert = match self.backend {
+            SqlBackend::Sqlite => {
+                "INSERT INTO login_attempts (key_hash, failures, last_failure, blocked_until) \
+                 VALUES (?, ?, ?, ?) ON CONFLICT (key_hash) DO UPDATE SET \
+                 failures = excluded.failures, last_failure = excluded.last_failure, \
+                 blocked_until = excluded.blocked_until"
+            }
+            SqlBackend::MySql => {
+                "INSERT INTO login_attempts (key_hash, failures, last_failure, blocked_until) \
+                 VALUES (?, ?, ?, ?) ON DUPLICATE KEY UPDATE \
+                 failures = VALUES(failures), last_failure = VALUES(last_failure), \
+                 blocked_until = VALUES(blocked_until)"
+            }
+        };
+        sqlx::query(upsert)
+            .bind(&attempts.key_hash()[..])
+            .bind(attempts.failures() as i64)
+            .bind(to_millis(attempts.last_failure()))
+            .bind(to_millis(attempts.blocked_until()))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn delete_login_attempts(&self, key_hash: &AttemptKeyHash) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM login_attempts WHERE key_hash = ?")
+            .bind(&key_hash[..])
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn delete_stale_login_attempts(
+        &self,
+        failed_before: SystemTime,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        let result =
+            sqlx::query("DELETE FROM login_attempts WHERE last_failure < ? AND blocked_until <= ?")
+                .bind(to_millis(failed_before))
+                .bind(to_millis(now))
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() as usize)
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+    time::SystemTime,
+};
+
+use hyper::Uri;
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::structs::{AppError, assets::Assets, user::UserProfile};
+
+/// Renders the pages of an `Assets` source with minijinja.
+///
+/// Pages extend `layout.html` and pull shared pieces from `partials/`.
+/// Everything ending in `.html` is auto-escaped. A template is compiled the
+/// first time it is used and cached afterwards, `load_all` does that up front.
+/// Templates made with `from_dir` are checked for changes before each render
+/// and recompiled when something was edited. That walks the directory on every
+/// page, so it is only for development, `from_assets` never looks again.
+pub struct Templates {
+    env: RwLock<Environment<'static>>,
+    assets: Assets,
+    //Newest file time the cache was built from, only when watching
+    seen: Mutex<Option<SystemTime>>,
+    watch: bool,
+}
+
+impl Templates {
+    /// Templates in the directory `dir`, reloaded on change.
+    pub fn from_dir(dir: impl Into<PathBuf>) -> Self {
+        let mut templates = Self::from_assets(Assets::Disk(dir.into()));
+        templates.seen = Mutex::new(templates.assets.last_modified());
+        templates.watch = true;
+        templates
+    }
+    /// Templates read once from `assets`, later edits need a restart.
+    pub fn from_assets(assets: Assets) -> Self {
+        let mut env = Environment::new();
+        let source = assets.clone();
+        env.set_loader(move |name| Ok(source.read_to_string(name)));
+        Self {
+            env: RwLock::new(env),
+            seen: Mutex::new(None),
+            assets,
+            watch: false,
+        }
+    }
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+    /// Compiles the given templates so a broken one stops startup
+    /// instead of failing its first request.
+    pub fn load_all(&self, names: &[&str]) -> Result<(), AppError> {
+        self.reload_if_changed();
+        let env = self.env.read().unwrap();
+        for name in names {
+            env.get_template(name).map_err(template_error)?;
+        }
+        Ok(())
+    }
+    pub fn render(&self, name: &str, context: &PageContext) -> Result<String, AppError> {
+        self.reload_if_changed();
+        self.env
+            .read()
+            .unwrap()
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .map_err(template_error)
+    }
+
+    fn reload_if_changed(&self) {
+        if !self.watch {
+            return;
+        }
+        let Some(newest) = self.assets.last_modified() else {
+            return;
+        };
+        let mut seen = self.seen.lock().unwrap();
+        if *seen != Some(newest) {
+            tracing::info!("pages changed, reloading templates");
+            self.env.write().unwrap().clear_templates();
+            *seen = Some(newest);
+        }
+    }
+}
+
+fn template_error(err: minijinja::Error) -> AppError {
+    AppError::Internal(format!("Template {:#}", err))
+}
+
+////////////////////////////////////////////////////////////////////
+/// What every page can read: `user`, `flash` and `csrf_token`.
+#[derive(Serialize, Default, Debug)]
+pub struct PageContext {
+    user: Option<UserProfile>,
+    flash: Option<Flash>,
+    csrf_token: Option<String>,
+}
+
+impl PageContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_user(mut self, user: UserProfile) -> Self {
+        self.user = Some(user);
+        self
+    }
+    pub fn with_flash(mut self, flash: Option<Flash>) -> Self {
+        self.flash = flash;
+        self
+    }
+    pub fn with_csrf_token(mut self, token: &str) -> Self {
+        self.csrf_token = Some(token.to_string());
+        self
+    }
+}
+
+/// A one-off message shown above a page, picked by `?flash=<code>`.
+///
+/// Only known codes are shown and the text is ours,
+/// so a crafted link can't put its own words on the page.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Flash {
+    kind: &'static str,
+    message: &'static str,
+}
+
+impl Flash {
+    pub const LOGGED_OUT: &str = "logged_out";
+    pub const REGISTERED: &str = "registered";
+    pub const PROFILE_UPDATED: &str = "profile_updated";
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        let (kind, message) = match code {
+            Flash::LOGGED_OUT => ("info", "You have been logged out."),
+            Flash::REGISTERED => ("success", "Account created, you can log in now."),
+            Flash::PROFILE_UPDATED => ("success", "Your profile has been saved."),
+            _ => return None,
+        };
+        Some(Self { kind, message })
+    }
+    /// The flash named by the `flash` query parameter of `uri`, if any.
+    pub fn from_uri(uri: &Uri) -> Option<Self> {
+        let query = uri.query()?;
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "flash")
+            .and_then(|(_, code)| Flash::from_code(&code))
+    }
+    pub fn kind(&self) -> &str {
+        self.kind
+    }
+    pub fn message(&self) -> &str {
+        self.message
+    }
+}
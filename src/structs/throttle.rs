@@ -0,0 +1,188 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::structs::user::normalize_email;
+
+////////////////////////////////////////////////////////////////////
+struct ThrottleConsts {}
+impl ThrottleConsts {
+    //Typos are free, the backoff starts with the failure after these
+    const FREE_FAILURES: u32 = 2;
+}
+
+/// Limits on failed logins, the `[login]` section of the config.
+///
+/// Every account and every client address has a counter of failures in a row.
+/// After a couple of them an account has to wait before the next try, twice as long
+/// each time, and at `max_failures` it is locked for `lockout_secs`. An address is
+/// locked at `address_max_failures` without a backoff, it may be a shared one.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginLimits {
+    /// Failed logins in a row before an account is locked.
+    pub max_failures: u32,
+    pub lockout_secs: u64,
+    /// First wait of the backoff, doubled with every further failure.
+    pub backoff_base_ms: u64,
+    /// Failed logins from one address before it is locked, 0 for no limit.
+    pub address_max_failures: u32,
+    /// Failures are forgotten after this long without another one.
+    pub window_secs: u64,
+}
+
+impl Default for LoginLimits {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            lockout_secs: 15 * 60,
+            backoff_base_ms: 1000,
+            address_max_failures: 20,
+            window_secs: 15 * 60,
+        }
+    }
+}
+
+impl LoginLimits {
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_secs)
+    }
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    /// The counters a login for `email` from `client` goes against.
+    pub fn keys(&self, email: &str, client: Option<IpAddr>) -> Vec<AttemptKey> {
+        let mut keys = vec![AttemptKey::Account(normalize_email(email))];
+        if let Some(ip) = client
+            && self.address_max_failures > 0
+        {
+            keys.push(AttemptKey::Address(ip));
+        }
+        keys
+    }
+
+    /// Counts one more failure on `attempts` and blocks it for as long as that calls for.
+    pub fn record_failure(&self, attempts: &mut LoginAttempts, key: &AttemptKey, now: SystemTime) {
+        let forgotten = now
+            .duration_since(attempts.last_failure)
+            .is_ok_and(|since| since >= self.window());
+        if forgotten {
+            attempts.failures = 0;
+        }
+        attempts.failures = attempts.failures.saturating_add(1);
+        attempts.last_failure = now;
+
+        let wait = match key {
+            AttemptKey::Account(_) if attempts.failures >= self.max_failures => self.lockout(),
+            AttemptKey::Account(_) => self.backoff(attempts.failures),
+            AttemptKey::Address(_) if attempts.failures >= self.address_max_failures => {
+                self.lockout()
+            }
+            AttemptKey::Address(_) => Duration::ZERO,
+        };
+        attempts.blocked_until = attempts.blocked_until.max(now + wait);
+    }
+
+    //Zero for the free failures, then the base doubling up to the lockout
+    fn backoff(&self, failures: u32) -> Duration {
+        let Some(doublings) = failures.checked_sub(ThrottleConsts::FREE_FAILURES + 1) else {
+            return Duration::ZERO;
+        };
+        let factor = 1u64.checked_shl(doublings).unwrap_or(u64::MAX);
+        Duration::from_millis(self.backoff_base_ms.saturating_mul(factor)).min(self.lockout())
+    }
+
+    /// What is wrong with the limits themselves, empty when they are usable.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_failures == 0 {
+            problems.push("login.max_failures must be above 0".to_string());
+        }
+        if self.lockout_secs == 0 {
+            problems.push("login.lockout_secs must be above 0".to_string());
+        }
+        if self.window_secs == 0 {
+            problems.push("login.window_secs must be above 0".to_string());
+        }
+        problems
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+/// What a failure counter is kept for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttemptKey {
+    /// Normalized email, whether or not such a user exists.
+    Account(String),
+    Address(IpAddr),
+}
+
+pub type AttemptKeyHash = [u8; 32];
+
+impl AttemptKey {
+    /// Counters are stored under this hash, so the table holds no emails or addresses.
+    pub fn hash(&self) -> AttemptKeyHash {
+        let key = match self {
+            AttemptKey::Account(email) => format!("account:{}", email),
+            AttemptKey::Address(ip) => format!("address:{}", ip),
+        };
+        Sha256::digest(key.as_bytes()).into()
+    }
+}
+
+/// Failed logins in a row for one `AttemptKey`, and until when it is blocked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoginAttempts {
+    key_hash: AttemptKeyHash,
+    failures: u32,
+    last_failure: SystemTime,
+    blocked_until: SystemTime,
+}
+
+impl LoginAttempts {
+    pub fn new(key: &AttemptKey) -> Self {
+        Self::from_parts(
+            key.hash(),
+            0,
+            SystemTime::UNIX_EPOCH,
+            SystemTime::UNIX_EPOCH,
+        )
+    }
+    pub fn from_parts(
+        key_hash: AttemptKeyHash,
+        failures: u32,
+        last_failure: SystemTime,
+        blocked_until: SystemTime,
+    ) -> Self {
+        Self {
+            key_hash,
+            failures,
+            last_failure,
+            blocked_until,
+        }
+    }
+    pub fn key_hash(&self) -> &AttemptKeyHash {
+        &self.key_hash
+    }
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+    pub fn last_failure(&self) -> SystemTime {
+        self.last_failure
+    }
+    pub fn blocked_until(&self) -> SystemTime {
+        self.blocked_until
+    }
+    /// How long until the next login may be tried, `None` when it may right away.
+    pub fn retry_after(&self, now: SystemTime) -> Option<Duration> {
+        self.blocked_until
+            .duration_since(now)
+            .ok()
+            .filter(|wait| !wait.is_zero())
+    }
+}
@@ -1,3 +1,24 @@
+use hyper::{Body, Response};
 use serde::de::DeserializeOwned;
 
 pub trait Extractable: DeserializeOwned + Sized {}
+
+/// Anything a handler can answer with.
+pub trait IntoResponse {
+    fn into_response(self) -> Response<Body>;
+}
+
+impl IntoResponse for Response<Body> {
+    fn into_response(self) -> Response<Body> {
+        self
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response<Body> {
+        match self {
+            Ok(ok) => ok.into_response(),
+            Err(err) => err.into_response(),
+        }
+    }
+}
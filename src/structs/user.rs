@@ -1,12 +1,23 @@
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 
 use serde::{Deserialize, Serialize};
 
-use crate::structs::{login::LoginInfo, traits::Extractable};
+use crate::structs::{
+    AppError,
+    login::LoginInfo,
+    password::{PasswordCheck, hash_password, spawn_hash_password, spawn_verify_password},
+    traits::Extractable,
+    validation::ValidationReport,
+};
 
 pub fn validate_email(new_email: &str) -> bool {
     !new_email.is_empty() && new_email.contains('@') && new_email.contains('.')
 }
+/// Canonical form used to store and compare emails, so `John@Doe.com `
+/// and `john@doe.com` are the same account.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
 pub fn validate_name(new_name: &str) -> bool {
     !new_name.is_empty() && new_name.len() >= UserConsts::MIN_NAME_LENGHT
 }
@@ -19,43 +30,168 @@ impl UserConsts {
     const MIN_NAME_LENGHT: usize = 2;
     const MIN_PASSWORD_LENGHT: usize = 8;
 }
+
+//Machine readable codes sent with every failing field
+pub struct ValidationCodes {}
+impl ValidationCodes {
+    pub const REQUIRED: &'static str = "required";
+    pub const TOO_LONG: &'static str = "too_long";
+    /// The password lacks a kind of character the policy asks for.
+    pub const WEAK: &'static str = "weak";
+    pub const TOO_SHORT: &'static str = "too_short";
+    pub const INVALID_FORMAT: &'static str = "invalid_format";
+}
+
+//The checks below add at most one error per field
+fn check_name(report: &mut ValidationReport, field: &'static str, label: &str, value: &str) {
+    if value.is_empty() {
+        report.add(
+            field,
+            ValidationCodes::REQUIRED,
+            format!("{} is required.", label),
+        );
+    } else if !validate_name(value) {
+        report.add(
+            field,
+            ValidationCodes::TOO_SHORT,
+            format!(
+                "{} must be at least {} characters long.",
+                label,
+                UserConsts::MIN_NAME_LENGHT
+            ),
+        );
+    }
+}
+pub(crate) fn check_email(report: &mut ValidationReport, value: &str) {
+    if value.is_empty() {
+        report.add(
+            "email",
+            ValidationCodes::REQUIRED,
+            "Email is required.".to_string(),
+        );
+    } else if !validate_email(value) {
+        report.add(
+            "email",
+            ValidationCodes::INVALID_FORMAT,
+            "Email must be valid (contain @ and .)".to_string(),
+        );
+    }
+}
+pub(crate) fn check_password(report: &mut ValidationReport, value: &str) {
+    if value.is_empty() {
+        report.add(
+            "password",
+            ValidationCodes::REQUIRED,
+            "Password is required.".to_string(),
+        );
+    } else if !validate_password(value) {
+        report.add(
+            "password",
+            ValidationCodes::TOO_SHORT,
+            format!(
+                "Password must be at least {} characters long.",
+                UserConsts::MIN_PASSWORD_LENGHT
+            ),
+        );
+    }
+}
 ////////////////////////////////////////////////////////////////////
+#[derive(Clone)]
 pub struct StoredUser {
     id: usize,
-    base: User,
+    first_name: String,
+    last_name: String,
+    email: String,
+    password_hash: String,
 }
 
 impl StoredUser {
-    pub fn new(id: usize, base: User) -> Result<Self, String> {
+    /// Builds a stored user from registration data, hashing the password.
+    pub fn new(id: usize, base: User) -> Result<Self, AppError> {
+        let password_hash = hash_password(base.password())?;
+
         Ok(Self {
-            id: id,
-            base: base.clone(),
+            id,
+            first_name: base.first_name,
+            last_name: base.last_name,
+            email: normalize_email(&base.email),
+            password_hash,
         })
     }
+    /// Rebuilds a stored user from already persisted fields (e.g. a database row).
+    pub fn from_parts(
+        id: usize,
+        first_name: &str,
+        last_name: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> Self {
+        Self {
+            id,
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            email: email.to_string(),
+            password_hash: password_hash.to_string(),
+        }
+    }
 
     pub fn get_user_profile(&self) -> UserProfile {
-        self.base.get_user_profile()
+        UserProfile {
+            first_name: self.first_name.clone(),
+            last_name: self.last_name.clone(),
+            email: self.email.clone(),
+        }
     }
 
     pub fn user_id(&self) -> usize {
         self.id
     }
+    pub fn first_name(&self) -> &str {
+        &self.first_name
+    }
+    pub fn last_name(&self) -> &str {
+        &self.last_name
+    }
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+    pub fn password_hash(&self) -> &str {
+        &self.password_hash
+    }
+    pub fn set_password_hash(&mut self, new_hash: String) {
+        self.password_hash = new_hash;
+    }
+
+    /// Replaces every field with the updated user data, re-hashing the password.
+    pub async fn update(&mut self, updated: &User) -> Result<(), AppError> {
+        updated.validate()?;
 
-    pub fn get_base(&self) -> &User {
-        &self.base
+        self.password_hash = spawn_hash_password(updated.password()).await?;
+        self.first_name = updated.first_name.clone();
+        self.last_name = updated.last_name.clone();
+        self.email = normalize_email(&updated.email);
+        Ok(())
     }
-    pub fn get_base_mut(&mut self) -> &mut User {
-        &mut self.base
+
+    pub async fn verify_credentials(&self, login: &LoginInfo) -> PasswordCheck {
+        if self.email != normalize_email(login.email()) {
+            return PasswordCheck::Invalid;
+        }
+        spawn_verify_password(login.password(), &self.password_hash).await
     }
 }
 
 impl Display for StoredUser {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Id: {} {}", self.id, self.base)
+        write!(
+            f,
+            "Id: {} User {} {} email {}",
+            self.id, self.first_name, self.last_name, self.email
+        )
     }
 }
 ////////////////////////////////////////////////////////////////////
-#[derive(Clone, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Clone, Deserialize, PartialEq, Eq)]
 pub struct User {
     first_name: String,
     last_name: String,
@@ -71,7 +207,7 @@ impl User {
         last_name: &str,
         email: &str,
         password: &str,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, AppError> {
         let user = User {
             first_name: first_name.to_string(),
             last_name: last_name.to_string(),
@@ -81,11 +217,15 @@ impl User {
         user.validate()?;
         Ok(user)
     }
-    pub fn copy_operator(&mut self, other: &User) -> Result<(), String> {
-        self.set_email(other.email.clone())?;
-        self.set_first_name(other.first_name.clone())?;
-        self.set_last_name(other.last_name.clone())?;
-        self.set_password(other.password.clone())?;
+    /// Same user with the email in its canonical form, see `normalize_email`.
+    pub fn with_normalized_email(mut self) -> Self {
+        self.email = normalize_email(&self.email);
+        self
+    }
+    pub fn copy_operator(&mut self, other: &User) -> Result<(), AppError> {
+        //All or nothing, and every bad field is reported at once
+        other.validate()?;
+        self.clone_from(other);
         Ok(())
     }
 
@@ -103,79 +243,58 @@ impl User {
     pub fn password(&self) -> &str {
         &self.password
     }
-    pub fn set_email(&mut self, new_email: String) -> Result<(), String> {
-        if validate_email(&new_email) {
-            return Err(String::from("Invalid email"));
-        }
+    pub fn set_email(&mut self, new_email: String) -> Result<(), AppError> {
+        let mut report = ValidationReport::new();
+        check_email(&mut report, &new_email);
+        report.into_result()?;
+
         self.email = new_email;
         Ok(())
     }
-    pub fn set_first_name(&mut self, new_first_name: String) -> Result<(), String> {
-        if validate_name(&new_first_name) {
-            return Err(String::from("Invalid email"));
-        }
+    pub fn set_first_name(&mut self, new_first_name: String) -> Result<(), AppError> {
+        let mut report = ValidationReport::new();
+        check_name(&mut report, "first_name", "First name", &new_first_name);
+        report.into_result()?;
+
         self.first_name = new_first_name;
         Ok(())
     }
-    pub fn set_last_name(&mut self, new_last_name: String) -> Result<(), String> {
-        if validate_name(&new_last_name) {
-            return Err(String::from("Invalid email"));
-        }
+    pub fn set_last_name(&mut self, new_last_name: String) -> Result<(), AppError> {
+        let mut report = ValidationReport::new();
+        check_name(&mut report, "last_name", "Last name", &new_last_name);
+        report.into_result()?;
+
         self.last_name = new_last_name;
         Ok(())
     }
-    pub fn set_password(&mut self, new_password: String) -> Result<(), String> {
-        if validate_password(&new_password) {
-            return Err(String::from("Invalid email"));
-        }
+    pub fn set_password(&mut self, new_password: String) -> Result<(), AppError> {
+        let mut report = ValidationReport::new();
+        check_password(&mut report, &new_password);
+        report.into_result()?;
+
         self.password = new_password;
         Ok(())
     }
 
     ////////////////////////////////////////////////
 
-    pub fn validate(&self) -> Result<(), String> {
-        //Simple validation
-
-        if !validate_name(&self.first_name) {
-            println!("First Name is {}", self.first_name);
-            return Err(format!(
-                "First name must be at least {} characters long.",
-                UserConsts::MIN_NAME_LENGHT
-            ));
-        }
-
-        if !validate_name(&self.last_name) {
-            println!("Last Name is {}", self.last_name);
-
-            return Err(format!(
-                "Last name must be at least {} characters long.",
-                UserConsts::MIN_NAME_LENGHT
-            ));
-        }
+    /// Checks every field and lists all of the failing ones.
+    pub fn validation_report(&self) -> ValidationReport {
+        let mut report = ValidationReport::new();
 
-        //should make a validation for repeated users with one email
-        if !validate_email(&self.email) {
-            println!("Email is {}", self.email);
-            return Err(format!("Email must be valid (contain @ and .)"));
-        }
+        check_name(&mut report, "first_name", "First name", &self.first_name);
+        check_name(&mut report, "last_name", "Last name", &self.last_name);
+        //Repeated emails are rejected by the store, see UserStore::insert_user
+        check_email(&mut report, &self.email);
+        check_password(&mut report, &self.password);
 
-        if !validate_password(&self.password) {
-            println!("Password is {}", self.password);
-            return Err(format!(
-                "Password must be at least {} characters long.",
-                UserConsts::MIN_PASSWORD_LENGHT
-            ));
-        }
-
-        Ok(())
+        report
     }
-
-    ////////////////////////////////////////////////
-    pub fn match_credentials(&self, login: &LoginInfo) -> bool {
-        self.email == login.email() && self.password == login.password()
+    pub fn validate(&self) -> Result<(), AppError> {
+        self.validation_report().into_result()
     }
 
+    ////////////////////////////////////////////////
     pub fn get_user_profile(&self) -> UserProfile {
         UserProfile {
             first_name: self.first_name.clone(),
@@ -189,12 +308,24 @@ impl Display for User {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "User {} {} email {} password {}",
-            self.first_name, self.last_name, self.email, self.password
+            "User {} {} email {}",
+            self.first_name, self.last_name, self.email
         )
     }
 }
 
+//Hand written so the plaintext password never ends up in debug output
+impl Debug for User {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("User")
+            .field("first_name", &self.first_name)
+            .field("last_name", &self.last_name)
+            .field("email", &self.email)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
 impl Extractable for User {}
 
 ////////////////////////////////////////////////////////////////////
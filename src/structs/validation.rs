@@ -0,0 +1,57 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::structs::AppError;
+
+/// One failing field. `code` is stable and meant for programs,
+/// `message` is meant for people.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Every failing field of a submitted form, not just the first one.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct ValidationReport {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&mut self, field: &'static str, code: &'static str, message: String) {
+        self.errors.push(FieldError {
+            field,
+            code,
+            message,
+        });
+    }
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+    pub fn has_field(&self, field: &str) -> bool {
+        self.errors.iter().any(|err| err.field == field)
+    }
+    /// `Ok` when nothing failed, otherwise the report as an `AppError::Validation`.
+    pub fn into_result(self) -> Result<(), AppError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        Err(AppError::Validation(self))
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<&str> = self.errors.iter().map(|err| err.field).collect();
+        write!(f, "invalid fields [{}]", fields.join(", "))
+    }
+}
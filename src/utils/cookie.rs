@@ -1,25 +1,329 @@
-use hyper::{Body, HeaderMap, Response, header};
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
 
-use crate::{structs::Constants, utils::response_bad_request};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use hyper::{HeaderMap, header};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
-pub fn extract_session_id_from_header(header: &HeaderMap) -> Result<String, Response<Body>> {
+use crate::{
+    config::Secret,
+    structs::{AppError, Constants, session::SessionConfig},
+};
+
+////////////////////////////////////////////////////////////////////
+pub struct CookieConsts {}
+impl CookieConsts {
+    /// Browsers only accept a cookie named like this when it is `Secure`,
+    /// has `Path=/` and no `Domain`, so a sub domain can't plant or overwrite it.
+    pub const HOST_PREFIX: &str = "__Host-";
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross-site requests too, browsers require `Secure` with it.
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+/// Attributes of the app's cookies, the `[cookie]` section of the config.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CookieConfig {
+    /// Only send the cookie over HTTPS.
+    pub secure: bool,
+    pub same_site: SameSite,
+    /// Share the cookie with sub domains, host only when unset.
+    pub domain: Option<String>,
+    /// Names the cookies `__Host-session_id` and so on, needs `secure` and no `domain`.
+    pub host_prefix: bool,
+    /// Appends an HMAC of every cookie to its value and drops cookies where it doesn't match.
+    /// Needs `secret`.
+    pub sign: bool,
+    /// Signs the cookies and the CSRF tokens, a random key per start when unset.
+    /// Instances behind one load balancer need the same one.
+    pub secret: Option<Secret>,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        Self {
+            secure: false,
+            same_site: SameSite::Lax,
+            domain: None,
+            host_prefix: false,
+            sign: false,
+            secret: None,
+        }
+    }
+}
+
+impl CookieConfig {
+    /// The name the cookie `name` goes by in the browser.
+    pub fn name(&self, name: &str) -> String {
+        match self.host_prefix {
+            true => format!("{}{}", CookieConsts::HOST_PREFIX, name),
+            false => name.to_string(),
+        }
+    }
+
+    /// The cookie `name` with the configured attributes, its value signed when `sign` is on.
+    /// It lasts until the browser closes unless given a `with_max_age`.
+    pub fn build(&self, name: &str, value: &str) -> SetCookie {
+        let name = self.name(name);
+        let value = match self.signer(&name) {
+            Some(mac) => format!("{}.{}", value, sign(mac, value)),
+            None => value.to_string(),
+        };
+        let cookie = SetCookie::new(name, value)
+            .with_secure(self.secure)
+            .with_same_site(self.same_site);
+        match &self.domain {
+            Some(domain) => cookie.with_domain(domain),
+            None => cookie,
+        }
+    }
+    /// Makes the browser drop the cookie `name`.
+    /// Domain and path have to match the ones it was set with, so they come from here too.
+    pub fn removal(&self, name: &str) -> SetCookie {
+        let mut cookie = self.build(name, "");
+        cookie.value.clear();
+        cookie.expired()
+    }
+
+    /// The value of the cookie `name` in `headers`, without a signature that doesn't match.
+    pub fn read(&self, headers: &HeaderMap, name: &str) -> Option<String> {
+        let name = self.name(name);
+        let value = cookie_value(headers, &name)?;
+        let Some(mut mac) = self.signer(&name) else {
+            return Some(value);
+        };
+        let (value, tag) = value.rsplit_once('.')?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+        mac.update(value.as_bytes());
+        mac.verify_slice(&tag).ok()?;
+        Some(value.to_string())
+    }
+
+    //Keyed with the name too, so a value can't be moved to another cookie.
+    //None without a secret as well, `Config::validate` refuses `sign` alone
+    fn signer(&self, name: &str) -> Option<Hmac<Sha256>> {
+        let secret = self.secret.as_ref().filter(|_| self.sign)?;
+        //Its own derivation, the same secret keys the CSRF tokens
+        let mut hasher = Sha256::new();
+        hasher.update(b"my_project cookie\0");
+        hasher.update(secret.expose().as_bytes());
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&hasher.finalize()).expect("HMAC takes any key length");
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        Some(mac)
+    }
+}
+
+fn sign(mut mac: Hmac<Sha256>, value: &str) -> String {
+    mac.update(value.as_bytes());
+    URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+////////////////////////////////////////////////////////////////////
+/// A `Set-Cookie` header value, `HttpOnly` with `Path=/` unless told otherwise.
+/// Its `Display` is the header value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    http_only: bool,
+    secure: bool,
+    same_site: Option<SameSite>,
+    path: String,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+}
+
+impl SetCookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            http_only: true,
+            secure: false,
+            same_site: None,
+            path: "/".to_string(),
+            domain: None,
+            max_age: None,
+            expires: None,
+        }
+    }
+    /// Hidden from page scripts, on by default.
+    pub fn with_http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+    pub fn with_domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+    /// Keeps the cookie for `max_age`, also sent as `Expires` for clients without `Max-Age`.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self.expires = Some(SystemTime::now() + max_age);
+        self
+    }
+    /// Tells the browser to drop the cookie right away.
+    pub fn expired(mut self) -> Self {
+        self.max_age = Some(Duration::ZERO);
+        self.expires = Some(SystemTime::UNIX_EPOCH);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+    /// Whether the header is one a browser keeps: a token for the name,
+    /// cookie octets for the value and the rules of a `__Host-` name followed.
+    pub fn is_valid(&self) -> bool {
+        let host_rules = !self.name.starts_with(CookieConsts::HOST_PREFIX)
+            || (self.secure && self.path == "/" && self.domain.is_none());
+        is_token(&self.name) && self.value.bytes().all(is_cookie_octet) && host_rules
+    }
+}
+
+impl Display for SetCookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        write!(f, "; Path={}", self.path)?;
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////
+/// Builds the `Set-Cookie` value for a freshly created session.
+/// The cookie lives as long as the session's absolute lifetime.
+pub fn session_cookie(
+    session_token: &str,
+    config: &SessionConfig,
+    cookie: &CookieConfig,
+) -> String {
+    cookie
+        .build(Constants::SESSION_COOKIE, session_token)
+        .with_max_age(config.absolute_lifetime())
+        .to_string()
+}
+
+/// `Set-Cookie` value that makes the browser drop the session cookie.
+pub fn expired_session_cookie(cookie: &CookieConfig) -> String {
+    cookie.removal(Constants::SESSION_COOKIE).to_string()
+}
+
+pub fn extract_session_id_from_header(
+    header: &HeaderMap,
+    cookie: &CookieConfig,
+) -> Result<String, AppError> {
     let Some(cookie_header) = header.get(header::COOKIE) else {
-        return Err(response_bad_request("No cookie found"));
+        return Err(AppError::Unauthorized("No cookie found".to_string()));
     };
 
-    let Ok(cookie_str) = cookie_header.to_str() else {
-        return Err(response_bad_request("Invalid cookie header"));
+    if cookie_header.to_str().is_err() {
+        return Err(AppError::BadRequest("Invalid cookie header".to_string()));
     };
 
-    let Some(session_id) = extract_session_id_from_cookie(cookie_str) else {
-        return Err(response_bad_request("No session ID in cookie"));
+    let Some(session_id) = cookie.read(header, Constants::SESSION_COOKIE) else {
+        return Err(AppError::Unauthorized(
+            "No session ID in cookie".to_string(),
+        ));
     };
     Ok(session_id)
 }
 
-fn extract_session_id_from_cookie(cookie_str: &str) -> Option<String> {
-    cookie_str
-        .split(';')
-        .map(str::trim)
-        .find_map(|el| el.strip_prefix(Constants::SESSION_ID_KEY).map(String::from))
+/// The raw value of the cookie called `name`, if the request sent it.
+/// The first one wins, browsers send the most specific path first.
+pub fn cookie_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(parse_cookies)
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// The `name=value` pairs of a `Cookie` header, in order, as RFC 6265 section 4.2 sends them.
+///
+/// Parsing is lenient the way section 5 asks of clients: whitespace around the
+/// pairs is ignored and a quoted value loses its quotes. Pairs without `=`,
+/// with a name that isn't a token or a value outside the cookie octets are skipped.
+pub fn parse_cookies(header: &str) -> impl Iterator<Item = (&str, &str)> {
+    header.split(';').filter_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        let name = name.trim_matches([' ', '\t']);
+        let value = value.trim_matches([' ', '\t']);
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        (is_token(name) && value.bytes().all(is_cookie_octet)).then_some((name, value))
+    })
+}
+
+//RFC 7230 tchar, no separators or controls
+fn is_token(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+//US-ASCII without controls, whitespace, DQUOTE, comma, semicolon and backslash
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
 }
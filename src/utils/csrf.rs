@@ -0,0 +1,157 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use hyper::{Body, Request, header};
+use rand::{RngCore, rngs::OsRng};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    structs::AppError,
+    utils::{cookie::CookieConfig, request::read_body_limited},
+};
+
+////////////////////////////////////////////////////////////////////
+pub struct CsrfConsts {}
+impl CsrfConsts {
+    pub const COOKIE: &str = "csrf_token";
+    pub const HEADER: &str = "x-csrf-token";
+    /// For plain form posts that can't set a header.
+    pub const FORM_FIELD: &str = "csrf_token";
+    const NONCE_LEN: usize = 16;
+    /// Largest form body read to find the field, the forms here are a few fields.
+    pub const MAX_FORM_BYTES: usize = 16 * 1024;
+}
+
+/// Signs and checks CSRF tokens.
+///
+/// A token is `<nonce>.<HMAC of the nonce and the session token>`, so it is only
+/// good for the session it was issued to and can't be made up without the key.
+/// It travels twice, in the `csrf_token` cookie and in the page, and a request
+/// only passes when both copies agree, see `middleware::CsrfProtection`.
+//No Debug, the key would show
+#[derive(Clone)]
+pub struct CsrfKey([u8; 32]);
+
+impl CsrfKey {
+    /// A key for this process only, tokens die with a restart.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+    /// The same key on every start and instance sharing `secret`.
+    pub fn from_secret(secret: &str) -> Self {
+        //Its own derivation, so the secret can sign other things without mixing them up
+        let mut hasher = Sha256::new();
+        hasher.update(b"my_project csrf\0");
+        hasher.update(secret.as_bytes());
+        Self(hasher.finalize().into())
+    }
+
+    /// A fresh token for the session `session_token`, empty when logged out.
+    pub fn issue(&self, session_token: &str) -> String {
+        let mut nonce = [0u8; CsrfConsts::NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let tag = self.mac(&nonce, session_token).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(nonce),
+            URL_SAFE_NO_PAD.encode(tag)
+        )
+    }
+    /// Whether `token` was issued by this key for `session_token`.
+    pub fn verify(&self, token: &str, session_token: &str) -> bool {
+        let Some((nonce, tag)) = token.split_once('.') else {
+            return false;
+        };
+        let (Ok(nonce), Ok(tag)) = (URL_SAFE_NO_PAD.decode(nonce), URL_SAFE_NO_PAD.decode(tag))
+        else {
+            return false;
+        };
+        nonce.len() == CsrfConsts::NONCE_LEN
+            && self.mac(&nonce, session_token).verify_slice(&tag).is_ok()
+    }
+
+    fn mac(&self, nonce: &[u8], session_token: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes any key length");
+        //The nonce has a fixed length, so nothing can shift between the two
+        mac.update(nonce);
+        mac.update(session_token.as_bytes());
+        mac
+    }
+}
+
+/// Both copies of a submitted token, compared without leaking how much matched.
+pub fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+////////////////////////////////////////////////////////////////////
+/// The CSRF token of the current request, put in the extensions by `CsrfProtection`.
+/// A new one is only sent as a cookie when a handler read it, e.g. to render a page.
+#[derive(Clone)]
+pub struct CsrfToken {
+    value: String,
+    used: Arc<AtomicBool>,
+}
+
+impl CsrfToken {
+    pub(crate) fn new(value: String) -> Self {
+        Self {
+            value,
+            used: Arc::new(AtomicBool::new(false)),
+        }
+    }
+    /// The token to embed in the page, e.g. with `PageContext::with_csrf_token`.
+    pub fn value(&self) -> &str {
+        self.used.store(true, Ordering::Relaxed);
+        &self.value
+    }
+    pub(crate) fn was_used(&self) -> bool {
+        self.used.load(Ordering::Relaxed)
+    }
+    pub fn of(request: &Request<Body>) -> Option<&CsrfToken> {
+        request.extensions().get::<CsrfToken>()
+    }
+}
+
+/// The token a request sent back: the `X-CSRF-Token` header,
+/// or the `csrf_token` field of a urlencoded form, whose body is read and put back.
+pub async fn submitted_token(
+    request: Request<Body>,
+) -> Result<(Request<Body>, Option<String>), AppError> {
+    if let Some(value) = request.headers().get(CsrfConsts::HEADER) {
+        let token = value.to_str().ok().map(String::from);
+        return Ok((request, token));
+    }
+
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return Ok((request, None));
+    }
+
+    //Read before the token is checked, so anyone could send it, keep it small
+    let (parts, body) = request.into_parts();
+    let bytes = read_body_limited(&parts.headers, body, CsrfConsts::MAX_FORM_BYTES).await?;
+    let token = form_urlencoded::parse(&bytes)
+        .find(|(key, _)| key == CsrfConsts::FORM_FIELD)
+        .map(|(_, value)| value.into_owned());
+    Ok((Request::from_parts(parts, Body::from(bytes)), token))
+}
+
+/// `Set-Cookie` value carrying the token, for as long as the browser runs.
+pub fn csrf_cookie(token: &str, cookie: &CookieConfig) -> String {
+    cookie.build(CsrfConsts::COOKIE, token).to_string()
+}
@@ -1,13 +1,13 @@
 pub mod cookie;
-pub mod load_statics;
+pub mod csrf;
 pub mod load_user;
+pub mod redirect;
 pub mod request;
 pub mod response;
+pub mod static_files;
 
-pub use load_statics::handle_static_file;
 pub use load_user::load_user_data;
-pub use response::response_bad_request;
 
 pub use request::deserialize_json_body;
 
-pub use cookie::extract_session_id_from_header;
+pub use cookie::{expired_session_cookie, extract_session_id_from_header, session_cookie};
@@ -0,0 +1,48 @@
+use hyper::Uri;
+
+use crate::structs::Routes;
+
+/// Keeps only same-origin targets: absolute paths like `/profile?tab=1`.
+/// Anything with a scheme, a host (`//evil.com`, `/\evil.com`) or control
+/// characters is refused, so `next` can't be used as an open redirect.
+pub fn safe_next(target: &str) -> Option<&str> {
+    let mut chars = target.chars();
+    if chars.next() != Some('/') {
+        return None;
+    }
+    if matches!(chars.next(), Some('/') | Some('\\')) {
+        return None;
+    }
+    if target.chars().any(|c| c.is_control()) {
+        return None;
+    }
+    Some(target)
+}
+
+/// The validated `next` query parameter of `uri`, if any.
+pub fn next_from_uri(uri: &Uri) -> Option<String> {
+    let query = uri.query()?;
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "next")
+        .and_then(|(_, value)| safe_next(&value).map(String::from))
+}
+
+/// Where to send someone who just logged in, `/home` unless a safe `next` was given.
+pub fn after_login_target(uri: &Uri) -> String {
+    next_from_uri(uri).unwrap_or_else(|| Routes::HOME.to_string())
+}
+
+/// `route` with a `?flash=` code for the page to show, see `Flash`.
+pub fn with_flash(route: &str, code: &str) -> String {
+    format!("{}?flash={}", route, code)
+}
+
+/// `/login?next=...` pointing back at `uri`.
+pub fn login_redirect_target(uri: &Uri) -> String {
+    let back_to = uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or(Routes::HOME);
+    let encoded: String = form_urlencoded::byte_serialize(back_to.as_bytes()).collect();
+    format!("{}?next={}", Routes::LOGIN, encoded)
+}
@@ -1,24 +1,61 @@
 use hyper::{
-    Body, Response,
-    body::{Bytes, to_bytes},
+    Body, HeaderMap,
+    body::{Bytes, HttpBody, to_bytes},
+    header,
 };
 
-use crate::{structs::traits::Extractable, utils::response_bad_request};
+use crate::structs::{AppError, traits::Extractable};
 
-pub async fn deserialize_json_body<T: Extractable>(body: Body) -> Result<T, Response<Body>> {
+pub async fn deserialize_json_body<T: Extractable>(body: Body) -> Result<T, AppError> {
     let body_in_bytes = to_bytes(body).await.map_err(|err| {
-        println!("->> Error in parsing request body {}", err);
+        tracing::debug!(error = %err, "couldn't read the request body");
 
-        response_bad_request("Could not parse request body to bytes")
+        AppError::BadRequest("Could not read the request body".to_string())
     })?;
 
     parse_json_struct(body_in_bytes)
 }
 
-fn parse_json_struct<T: Extractable>(bytes: Bytes) -> Result<T, Response<Body>> {
+/// Reads the whole body, but no more than `limit` bytes of it.
+/// A `Content-Length` over the limit is refused before anything is read.
+pub async fn read_body_limited(
+    headers: &HeaderMap,
+    mut body: Body,
+    limit: usize,
+) -> Result<Bytes, AppError> {
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit as u64) {
+        return Err(AppError::PayloadTooLarge(limit));
+    }
+
+    //Chunked bodies don't say how long they are, count while reading
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|err| {
+            tracing::debug!(error = %err, "couldn't read the request body");
+
+            AppError::BadRequest("Could not read the request body".to_string())
+        })?;
+        if bytes.len() + chunk.len() > limit {
+            return Err(AppError::PayloadTooLarge(limit));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(bytes))
+}
+
+fn parse_json_struct<T: Extractable>(bytes: Bytes) -> Result<T, AppError> {
     serde_json::from_slice(&bytes).map_err(|err| {
-        println!("->> Error in parsing json {}", err);
+        //Only the position, serde quotes the offending value and it may be a password
+        tracing::debug!(
+            line = err.line(),
+            column = err.column(),
+            "malformed JSON body"
+        );
 
-        response_bad_request("Could not parse body's bytes to an Extractable struct")
+        AppError::BadRequest(format!("Malformed JSON body: {}", err))
     })
 }
@@ -3,14 +3,6 @@ use hyper::{
     header::{self, HeaderValue},
 };
 
-pub fn response_bad_request(msg: &str) -> Response<Body> {
-    Response::builder()
-        .status(StatusCode::BAD_REQUEST)
-        .header("Content-Type", "text/plain")
-        .body(Body::from(msg.to_string()))
-        .unwrap()
-}
-
 pub fn redirect_with_cookie(cookie: &str, route: &str, body_text: &str) -> Response<Body> {
     Response::builder()
         .status(StatusCode::FOUND)
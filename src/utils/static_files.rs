@@ -0,0 +1,304 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{Body, Request, Response, StatusCode, header};
+use percent_encoding::percent_decode_str;
+
+use crate::{
+    router::HandlerResult,
+    structs::{
+        AppError,
+        assets::{AssetFile, Assets},
+    },
+};
+
+/// Serves the files of an `Assets` source, e.g. for `/static/{*path}`.
+///
+/// Answers with the Content-Type of the extension, `ETag` and `Last-Modified`
+/// validators (304 when they match), single `Range` requests and
+/// precompressed `.br`/`.gz` siblings when the client accepts them.
+/// Paths leaving the root, dot files and directories are a 404.
+pub struct StaticFiles {
+    assets: Assets,
+    cache_control: &'static str,
+}
+
+impl StaticFiles {
+    /// Files under the directory `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::from_assets(Assets::Disk(root.into()))
+    }
+    pub fn from_assets(assets: Assets) -> Self {
+        Self {
+            assets,
+            cache_control: "public, max-age=3600",
+        }
+    }
+    pub fn with_cache_control(mut self, cache_control: &'static str) -> Self {
+        self.cache_control = cache_control;
+        self
+    }
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    /// `path` is the still percent-encoded rest of the URL, e.g. `css/site.css`.
+    pub async fn serve(&self, request: &Request<Body>, path: &str) -> HandlerResult {
+        let not_found = || AppError::NotFound(format!("No static file {}", path));
+        let relative = resolve(Path::new(""), path).ok_or_else(not_found)?;
+        let content_type = mime_type(&relative);
+
+        //Ranges are counted on the bytes sent, so they only get the plain file
+        let wants_range = request.headers().contains_key(header::RANGE);
+        let mut chosen = None;
+        if !wants_range {
+            chosen = self.precompressed(request, &relative).await;
+        }
+        let (file, encoding) = match chosen {
+            Some(found) => found,
+            None => {
+                let file = self.assets.find(&relative).await.ok_or_else(not_found)?;
+                (file, None)
+            }
+        };
+
+        let modified = file.modified();
+        let etag = entity_tag(&file, encoding);
+        let mut response = Response::builder()
+            .header(header::ETAG, &etag)
+            .header(header::CACHE_CONTROL, self.cache_control)
+            .header(header::VARY, "Accept-Encoding");
+        if let Some(modified) = modified {
+            response = response.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+        }
+
+        if is_not_modified(request, &etag, modified) {
+            return Ok(response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap());
+        }
+
+        response = response
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::ACCEPT_RANGES, "bytes");
+        if let Some(encoding) = encoding {
+            response = response.header(header::CONTENT_ENCODING, encoding);
+        }
+
+        let len = file.len();
+        let range = match wants_range && if_range_matches(request, &etag, modified) {
+            true => header_str(request, header::RANGE).and_then(|value| parse_range(value, len)),
+            false => None,
+        };
+
+        match range {
+            None => {
+                let content = file.read().await.map_err(read_error)?;
+                Ok(response
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_LENGTH, content.len())
+                    .body(Body::from(content))
+                    .unwrap())
+            }
+            Some(Ok((start, end))) => {
+                let content = file.read_range(start, end).await.map_err(read_error)?;
+                Ok(response
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", start, end, len),
+                    )
+                    .header(header::CONTENT_LENGTH, content.len())
+                    .body(Body::from(content))
+                    .unwrap())
+            }
+            Some(Err(())) => Ok(response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())
+                .unwrap()),
+        }
+    }
+
+    async fn precompressed(
+        &self,
+        request: &Request<Body>,
+        relative: &Path,
+    ) -> Option<(AssetFile, Option<&'static str>)> {
+        for (encoding, suffix) in [("br", "br"), ("gzip", "gz")] {
+            if !accepts_encoding(request, encoding) {
+                continue;
+            }
+            let mut name = relative.as_os_str().to_owned();
+            name.push(".");
+            name.push(suffix);
+            if let Some(file) = self.assets.find(Path::new(&name)).await {
+                return Some((file, Some(encoding)));
+            }
+        }
+        None
+    }
+}
+
+/// Joins the percent-encoded `path` onto `root`, or `None` when any segment
+/// is empty, starts with a dot (`..`, `.env`) or smuggles a separator.
+pub fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = root.to_path_buf();
+    for segment in path.split('/') {
+        let segment = percent_decode_str(segment).decode_utf8().ok()?;
+        if segment.is_empty()
+            || segment.starts_with('.')
+            || segment.contains(['/', '\\', ':', '\0'])
+        {
+            return None;
+        }
+        resolved.push(segment.as_ref());
+    }
+    Some(resolved)
+}
+
+/// Content-Type picked from the file extension, `application/octet-stream` if unknown.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mp3") => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+/// One `bytes=` range as inclusive `(start, end)`.
+/// `None` means the header is ignored (malformed or several ranges),
+/// `Err` that it can't be satisfied for a body of `len` bytes.
+pub fn parse_range(value: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let Some(last) = len.checked_sub(1) else {
+        return Some(Err(()));
+    };
+    let range = match (start, end) {
+        ("", suffix) => {
+            let count: u64 = suffix.parse().ok()?;
+            if count == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(count), last)
+        }
+        (start, "") => (start.parse().ok()?, last),
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            (start, end.min(last))
+        }
+    };
+
+    if range.0 > last {
+        return Some(Err(()));
+    }
+    Some(Ok(range))
+}
+
+////////////////////////////////////////////////////////////////////
+fn header_str(request: &Request<Body>, name: header::HeaderName) -> Option<&str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+//Each encoding is its own representation with its own tag
+fn entity_tag(file: &AssetFile, encoding: Option<&str>) -> String {
+    match encoding {
+        Some(encoding) => format!("\"{}-{}\"", file.fingerprint(), encoding),
+        None => format!("\"{}\"", file.fingerprint()),
+    }
+}
+
+//Last-Modified only carries whole seconds
+fn whole_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or(0)
+}
+
+fn is_not_modified(request: &Request<Body>, etag: &str, modified: Option<SystemTime>) -> bool {
+    //If-None-Match wins over If-Modified-Since when both are sent
+    if let Some(tags) = header_str(request, header::IF_NONE_MATCH) {
+        return tags
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+    let since = header_str(request, header::IF_MODIFIED_SINCE)
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+    match (since, modified) {
+        (Some(since), Some(modified)) => whole_seconds(modified) <= whole_seconds(since),
+        _ => false,
+    }
+}
+
+fn if_range_matches(request: &Request<Body>, etag: &str, modified: Option<SystemTime>) -> bool {
+    let Some(value) = header_str(request, header::IF_RANGE) else {
+        return true;
+    };
+    if value.starts_with('"') {
+        return value == etag;
+    }
+    match (httpdate::parse_http_date(value), modified) {
+        (Ok(date), Some(modified)) => whole_seconds(modified) == whole_seconds(date),
+        _ => false,
+    }
+}
+
+fn accepts_encoding(request: &Request<Body>, encoding: &str) -> bool {
+    let Some(accepted) = header_str(request, header::ACCEPT_ENCODING) else {
+        return false;
+    };
+    accepted.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|param| param.strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        name.eq_ignore_ascii_case(encoding) && quality > 0.0
+    })
+}
+
+fn read_error(err: std::io::Error) -> AppError {
+    AppError::Internal(format!("Reading static file failed {}", err))
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use hyper::{StatusCode, body::to_bytes, header};
+use my_project::structs::{AppError, traits::IntoResponse, user::User};
+use serde_json::{Value, json};
+
+async fn problem(error: AppError) -> (StatusCode, Value) {
+    let response = error.into_response();
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+
+    let status = response.status();
+    let body = to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn status_codes() -> Result<()> {
+    let cases = [
+        (AppError::BadRequest("x".into()), StatusCode::BAD_REQUEST),
+        (AppError::Unauthorized("x".into()), StatusCode::UNAUTHORIZED),
+        (AppError::NotFound("x".into()), StatusCode::NOT_FOUND),
+        (AppError::Conflict("x".into()), StatusCode::CONFLICT),
+        (
+            AppError::PayloadTooLarge(1024),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+        (
+            AppError::TooManyRequests(Duration::from_millis(1500)),
+            StatusCode::TOO_MANY_REQUESTS,
+        ),
+        (
+            AppError::Internal("x".into()),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+        (
+            AppError::Storage(sqlx::Error::PoolTimedOut),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    ];
+    for (error, status) in cases {
+        assert_eq!(error.status(), status);
+        assert_eq!(problem(error).await.0, status);
+    }
+
+    //Whole seconds, rounded up
+    let response = AppError::TooManyRequests(Duration::from_millis(1500)).into_response();
+    assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn problem_body() -> Result<()> {
+    let (_, body) = problem(AppError::Conflict("Email is already registered".into())).await;
+    assert_eq!(
+        body,
+        json!({
+            "type": "about:blank",
+            "title": "Conflict",
+            "status": 409,
+            "detail": "Email is already registered",
+        })
+    );
+
+    //Validation errors list every failing field
+    let error = User::new("J", "Doe", "jd", "12345678").unwrap_err();
+    let (status, body) = problem(error).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["errors"],
+        json!([
+            {
+                "field": "first_name",
+                "code": "too_short",
+                "message": "First name must be at least 2 characters long.",
+            },
+            {
+                "field": "email",
+                "code": "invalid_format",
+                "message": "Email must be valid (contain @ and .)",
+            },
+        ])
+    );
+
+    //Storage failures don't leak database details
+    let (_, body) = problem(AppError::Storage(sqlx::Error::PoolTimedOut)).await;
+    assert!(!body["detail"].as_str().unwrap().contains("pool"));
+
+    Ok(())
+}
@@ -3,7 +3,7 @@ use my_project::structs::{app_state::AppState, login::LoginInfo, user::User};
 
 #[tokio::test]
 async fn app_state_testing() -> Result<()> {
-    let mut state = AppState::new_without_db().unwrap();
+    let mut state = AppState::new_in_memory();
 
     /////////////////////////////////////////////////////////
     let user = User::new("John", "Doe", "j@d.c", "12345678").unwrap();
@@ -40,15 +40,14 @@ async fn app_state_testing() -> Result<()> {
     //////////////////////////////////////////////////////////
     //Session chekcing
 
-    //For now session_id = user_id
+    //The session id is an unguessable token handed back by add_session
     let user_id: usize = 0;
-    let session_id = user_id;
-    let session_id_str = session_id.to_string();
-    let invalid_session_id = 1.to_string();
+    let invalid_session_id = "0".to_string();
 
     let session_result = state.add_session(user_id).await;
     assert!(session_result.is_ok());
     assert_eq!(state.print_session_count().await, 1);
+    let session_id_str = session_result.unwrap();
 
     assert!(!state.is_session_valid(&invalid_session_id).await);
     assert!(state.is_session_valid(&session_id_str).await);
@@ -66,7 +65,7 @@ async fn app_state_testing() -> Result<()> {
             .get_user_id_from_session(&session_id_str)
             .await
             .unwrap(),
-        session_id
+        user_id
     );
     //////////////////////////////////////////////////////////
     assert!(
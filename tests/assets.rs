@@ -0,0 +1,138 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use hyper::{Body, Request, StatusCode};
+use my_project::{
+    handlers::app_router,
+    structs::{
+        Pages,
+        app_state::AppState,
+        assets::Assets,
+        templates::{PageContext, Templates},
+    },
+};
+
+fn page_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_project_assets_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("static")).unwrap();
+    dir
+}
+
+//Some file systems only keep whole seconds, so the edit is dated explicitly
+fn write_later(path: &Path, content: &str, seconds: u64) {
+    fs::write(path, content).unwrap();
+    let time = SystemTime::now() + Duration::from_secs(seconds);
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(time)
+        .unwrap();
+}
+
+#[test]
+fn disk_templates_reload_on_change() -> Result<()> {
+    let dir = page_dir("reload");
+    fs::write(dir.join("page.html"), "v1")?;
+
+    let templates = Templates::from_dir(&dir);
+    assert_eq!(templates.render("page.html", &PageContext::new())?, "v1");
+
+    write_later(&dir.join("page.html"), "v2", 5);
+    assert_eq!(templates.render("page.html", &PageContext::new())?, "v2");
+
+    //New partials are picked up too
+    write_later(&dir.join("page.html"), "{% include \"part.html\" %}", 10);
+    write_later(&dir.join("part.html"), "partial", 10);
+    assert_eq!(
+        templates.render("page.html", &PageContext::new())?,
+        "partial"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn asset_templates_are_read_once() -> Result<()> {
+    let dir = page_dir("read_once");
+    fs::write(dir.join("page.html"), "v1")?;
+
+    let templates = Templates::from_assets(Assets::Disk(dir.clone()));
+    assert_eq!(templates.render("page.html", &PageContext::new())?, "v1");
+
+    write_later(&dir.join("page.html"), "v2", 5);
+    assert_eq!(templates.render("page.html", &PageContext::new())?, "v1");
+
+    Ok(())
+}
+
+#[test]
+fn asset_names_stay_inside() {
+    let assets = Assets::Disk(PathBuf::from(Pages::DIR));
+    assert!(assets.read_to_string(Pages::LOGIN).is_some());
+    assert!(assets.read_to_string("../Cargo.toml").is_none());
+    assert!(assets.read_to_string("static/../layout.html").is_none());
+    assert_eq!(
+        assets.join(Pages::STATIC_DIR),
+        Assets::Disk(PathBuf::from("pages/static"))
+    );
+    assert_eq!(
+        Assets::select(Some(PathBuf::from("/srv/pages"))),
+        Assets::Disk(PathBuf::from("/srv/pages"))
+    );
+}
+
+#[tokio::test]
+async fn pages_dir_is_configurable() -> Result<()> {
+    let dir = page_dir("configured");
+    for page in Pages::ALL {
+        fs::write(dir.join(page), format!("custom {}", page))?;
+    }
+    fs::write(dir.join("static/app.js"), "console.log(1)")?;
+
+    let state = AppState::new_in_memory().with_assets(Assets::Disk(dir));
+    let router = app_router();
+
+    let request = Request::builder().uri("/login").body(Body::empty())?;
+    let response = router.dispatch(request, state.clone()).await;
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    assert_eq!(body, "custom login.html");
+
+    let request = Request::builder()
+        .uri("/static/app.js")
+        .body(Body::empty())?;
+    let response = router.dispatch(request, state.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::builder()
+        .uri("/static/loginPageStyle.css")
+        .body(Body::empty())?;
+    let response = router.dispatch(request, state).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[cfg(feature = "embed")]
+#[tokio::test]
+async fn embedded_pages_need_no_directory() -> Result<()> {
+    let assets = Assets::default();
+    assert!(assets.is_embedded());
+    Templates::from_assets(assets.clone()).load_all(&Pages::ALL)?;
+
+    let file = assets
+        .join(Pages::STATIC_DIR)
+        .find(Path::new("loginPageStyle.css"))
+        .await
+        .expect("css is embedded");
+    assert!(file.modified().is_none());
+    assert_eq!(file.len(), file.read().await?.len() as u64);
+    assert_eq!(&file.read_range(0, 3).await?[..], &file.read().await?[..4]);
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use std::{fs, net::SocketAddr, path::PathBuf};
+
+use anyhow::Result;
+use clap::Parser;
+use my_project::{
+    cli::Cli,
+    config::{Config, ConfigError, LogFormat, LogLevel, Secret, StorageBackend},
+    structs::{app_state::AppState, user::User},
+    utils::cookie::SameSite,
+};
+
+fn config_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("my_project_config_{}.toml", name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn problems(err: ConfigError) -> Vec<String> {
+    match err {
+        ConfigError::Invalid(problems) => problems,
+        other => panic!("Expected invalid config, got {}", other),
+    }
+}
+
+#[test]
+fn defaults_and_example_are_valid() -> Result<()> {
+    let config = Config::load(None, env(&[]))?;
+    config.validate()?;
+    assert_eq!(config, Config::default());
+
+    //The documented example spells out the defaults
+    let example = Config::load(Some("my_project.example.toml".as_ref()), env(&[]))?;
+    assert_eq!(example, Config::default());
+
+    Ok(())
+}
+
+#[test]
+fn layers_override_each_other() -> Result<()> {
+    let file = config_file(
+        "layers",
+        r#"
+        [server]
+        listen = "0.0.0.0:8000"
+
+        [storage]
+        backend = "memory"
+
+        [session]
+        idle_timeout_secs = 600
+
+        [cookie]
+        secure = true
+        "#,
+    );
+    let mut config = Config::load(
+        Some(&file),
+        env(&[
+            ("MY_PROJECT_SERVER_LISTEN", "0.0.0.0:9000"),
+            ("MY_PROJECT_COOKIE_SAME_SITE", "strict"),
+            ("MY_PROJECT_PASSWORD_REQUIRE_DIGIT", "true"),
+            ("MY_PROJECT_CONFIG", "ignored.toml"),
+            ("UNRELATED", "x"),
+        ]),
+    )?;
+    assert_eq!(config.server.listen, "0.0.0.0:9000".parse::<SocketAddr>()?);
+    assert_eq!(config.storage.backend, StorageBackend::Memory);
+    assert_eq!(config.session.idle_timeout_secs, 600);
+    assert_eq!(config.session.absolute_lifetime_secs, 86400);
+    assert!(config.cookie.secure);
+    assert_eq!(config.cookie.same_site, SameSite::Strict);
+    assert!(config.password.require_digit);
+
+    //Flags win over everything
+    let cli = Cli::try_parse_from([
+        "my_project",
+        "--listen",
+        "127.0.0.1:7000",
+        "--storage",
+        "sql_cached",
+        "--database-url",
+        "sqlite::memory:",
+        "--log-level",
+        "debug",
+        "--log-format",
+        "json",
+    ])?;
+    cli.apply_to(&mut config);
+    config.validate()?;
+    assert_eq!(
+        config.server.listen,
+        "127.0.0.1:7000".parse::<SocketAddr>()?
+    );
+    assert_eq!(config.storage.backend, StorageBackend::SqlCached);
+    assert_eq!(config.storage.url, "sqlite::memory:");
+    assert_eq!(config.log.level, LogLevel::Debug);
+    assert_eq!(config.log.format, LogFormat::Json);
+
+    Ok(())
+}
+
+#[test]
+fn clear_errors() {
+    let file = config_file("broken", "[server\nlisten = 1");
+    let err = Config::load(Some(&file), env(&[])).unwrap_err();
+    assert!(matches!(err, ConfigError::Syntax { .. }), "{}", err);
+
+    let missing = Config::load(Some("/nonexistent/my_project.toml".as_ref()), env(&[]));
+    assert!(matches!(missing, Err(ConfigError::Read { .. })));
+
+    //Wrong types and unknown names list the key they are about
+    let err = Config::load(
+        None,
+        env(&[
+            ("MY_PROJECT_SESSION_IDLE_TIMEOUT_SECS", "soon"),
+            ("MY_PROJECT_SERVER_PORT", "80"),
+            ("MY_PROJECT_NOPE_KEY", "1"),
+        ]),
+    )
+    .unwrap_err();
+    let problems = problems(err);
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(
+        problems
+            .iter()
+            .any(|p| p.starts_with("[session] idle_timeout_secs:"))
+    );
+    assert!(problems.iter().any(|p| p.contains("unknown field `port`")));
+    assert!(problems.iter().any(|p| p == "[nope] unknown section"));
+
+    let err = Config::load(None, env(&[("MY_PROJECT_LISTEN", "x")])).unwrap_err();
+    assert!(matches!(err, ConfigError::Syntax { .. }));
+}
+
+#[test]
+fn validation_reports_every_problem() -> Result<()> {
+    let mut config = Config::default();
+    config.session.idle_timeout_secs = config.session.absolute_lifetime_secs + 1;
+    config.storage.url = "postgres://admin:hunter2@db".to_string();
+    config.cookie.same_site = SameSite::None;
+    config.cookie.domain = Some("a b".to_string());
+    config.password.min_length = 4;
+    config.server.pages_dir = Some(PathBuf::from("/nonexistent/pages"));
+
+    let problems = problems(config.validate().unwrap_err());
+    assert_eq!(problems.len(), 6, "{:?}", problems);
+    assert!(problems.iter().any(|p| p.ends_with("got postgres")));
+    assert!(!problems.iter().any(|p| p.contains("hunter2")));
+
+    //The url doesn't matter without a database
+    let mut config = Config::default();
+    config.storage.backend = StorageBackend::Memory;
+    config.storage.url = String::new();
+    config.validate()?;
+
+    //Asking for metrics the binary can't serve
+    let mut config = Config::default();
+    config.metrics.enabled = true;
+    assert_eq!(config.validate().is_ok(), cfg!(feature = "metrics"));
+
+    Ok(())
+}
+
+#[test]
+fn cookie_hardening_needs_its_settings() -> Result<()> {
+    //Signing without a key, a __Host- cookie the browser would drop
+    let mut config = Config::default();
+    config.cookie.sign = true;
+    config.cookie.host_prefix = true;
+    let problems = problems(config.validate().unwrap_err());
+    assert_eq!(problems.len(), 2, "{:?}", problems);
+
+    config.cookie.secret = Some(Secret::new("a secret that is long enough to be used"));
+    config.cookie.secure = true;
+    config.validate()?;
+    Ok(())
+}
+
+#[test]
+fn env_strings_stay_strings() -> Result<()> {
+    //Only keys that take numbers or booleans read the value as TOML
+    let secret = "1234567890123456789012345678901.5";
+    let config = Config::load(
+        None,
+        env(&[
+            ("MY_PROJECT_COOKIE_SECRET", secret),
+            ("MY_PROJECT_COOKIE_SIGN", "true"),
+            ("MY_PROJECT_STORAGE_URL", "1979-05-27"),
+            ("MY_PROJECT_SESSION_IDLE_TIMEOUT_SECS", "600"),
+        ]),
+    )?;
+    assert_eq!(config.cookie.secret, Some(Secret::new(secret)));
+    assert!(config.cookie.sign);
+    assert_eq!(config.storage.url, "1979-05-27");
+    assert_eq!(config.session.idle_timeout_secs, 600);
+    Ok(())
+}
+
+#[tokio::test]
+async fn state_follows_config() -> Result<()> {
+    let mut config = Config::default();
+    config.storage.backend = StorageBackend::Memory;
+    config.password.min_length = 12;
+    config.password.require_digit = true;
+    config.cookie.secure = true;
+    config.session.absolute_lifetime_secs = 120;
+    config.session.idle_timeout_secs = 60;
+
+    let state = AppState::from_config(&config).await?;
+    assert_eq!(state.session_config().idle_timeout().as_secs(), 60);
+    assert!(state.cookie_config().secure);
+
+    let short = User::new("John", "Doe", "j@d.c", "abcdefghij").unwrap();
+    let err = state.add_user(short).await.unwrap_err();
+    assert!(err.to_string().contains("password"), "{}", err);
+
+    let no_digit = User::new("John", "Doe", "j@d.c", "abcdefghijkl").unwrap();
+    assert!(state.add_user(no_digit).await.is_err());
+
+    let fine = User::new("John", "Doe", "j@d.c", "abcdefghijk1").unwrap();
+    state.add_user(fine).await?;
+
+    Ok(())
+}
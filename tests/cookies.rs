@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use hyper::{Body, HeaderMap, Method, Request, StatusCode, header};
+use my_project::{
+    config::Secret,
+    handlers::app_router,
+    structs::app_state::AppState,
+    utils::cookie::{CookieConfig, SameSite, SetCookie, cookie_value, parse_cookies},
+};
+
+fn cookie_headers(cookies: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, cookies.parse().unwrap());
+    headers
+}
+
+fn signing_config() -> CookieConfig {
+    CookieConfig {
+        secure: true,
+        host_prefix: true,
+        sign: true,
+        secret: Some(Secret::new("a secret that is long enough to be used")),
+        ..CookieConfig::default()
+    }
+}
+
+#[test]
+fn builder_writes_the_attributes() {
+    let cookie = SetCookie::new("id", "abc")
+        .with_secure(true)
+        .with_same_site(SameSite::Strict)
+        .with_domain("example.com")
+        .with_max_age(Duration::from_secs(60));
+    let header = cookie.to_string();
+    assert!(header.starts_with("id=abc; HttpOnly; Path=/; SameSite=Strict; Secure"));
+    assert!(header.contains("; Domain=example.com; Max-Age=60; Expires="));
+    assert!(cookie.is_valid());
+
+    let removal = SetCookie::new("id", "").expired().to_string();
+    assert_eq!(
+        removal,
+        "id=; HttpOnly; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+    );
+
+    //Nothing that would end the header or the pair early
+    assert!(!SetCookie::new("id", "a;b").is_valid());
+    assert!(!SetCookie::new("i d", "a").is_valid());
+    assert!(!SetCookie::new("__Host-id", "a").is_valid());
+    assert!(
+        !SetCookie::new("__Host-id", "a")
+            .with_secure(true)
+            .with_domain("example.com")
+            .is_valid()
+    );
+}
+
+#[test]
+fn parser_follows_rfc_6265() {
+    let pairs: Vec<_> =
+        parse_cookies(r#"a=1; b="two";c=; bad; =x; d e=4; f=a,b;  g = 7 "#).collect();
+    assert_eq!(pairs, [("a", "1"), ("b", "two"), ("c", ""), ("g", "7")]);
+
+    //HTTP/2 splits the cookies over several headers, the first of a name wins
+    let mut headers = cookie_headers("x=1");
+    headers.append(header::COOKIE, "y=2; x=3".parse().unwrap());
+    assert_eq!(cookie_value(&headers, "y").as_deref(), Some("2"));
+    assert_eq!(cookie_value(&headers, "x").as_deref(), Some("1"));
+    assert_eq!(cookie_value(&headers, "z"), None);
+}
+
+#[test]
+fn signed_cookies_reject_tampering() {
+    let config = signing_config();
+    let cookie = config.build("session_id", "token");
+    assert_eq!(cookie.name(), "__Host-session_id");
+    assert!(cookie.value().starts_with("token."));
+    assert!(cookie.is_valid());
+
+    let sent = format!("{}={}", cookie.name(), cookie.value());
+    assert_eq!(
+        config.read(&cookie_headers(&sent), "session_id").as_deref(),
+        Some("token")
+    );
+
+    //Changed value, unsigned, moved to another name, or without the prefix
+    let tag = cookie.value().rsplit_once('.').unwrap().1;
+    let forged = format!("__Host-session_id=other.{}", tag);
+    assert_eq!(config.read(&cookie_headers(&forged), "session_id"), None);
+    let unsigned = cookie_headers("__Host-session_id=token");
+    assert_eq!(config.read(&unsigned, "session_id"), None);
+    let moved = format!("__Host-csrf_token={}", cookie.value());
+    assert_eq!(config.read(&cookie_headers(&moved), "csrf_token"), None);
+    let bare = format!("session_id={}", cookie.value());
+    assert_eq!(config.read(&cookie_headers(&bare), "session_id"), None);
+
+    //Another secret, another signature
+    let other = CookieConfig {
+        secret: Some(Secret::new("another secret that is long enough too")),
+        ..signing_config()
+    };
+    assert_eq!(other.read(&cookie_headers(&sent), "session_id"), None);
+
+    let removal = config.removal("session_id").to_string();
+    assert!(removal.starts_with("__Host-session_id=; HttpOnly; Path=/; SameSite=Lax; Secure;"));
+}
+
+#[tokio::test]
+async fn sessions_ride_on_signed_host_cookies() -> Result<()> {
+    let router = app_router();
+    let config = signing_config();
+    let state = AppState::new_in_memory().with_cookie_config(config.clone());
+
+    let csrf = state.csrf_key().issue("");
+    let signed_csrf = config.build("csrf_token", &csrf);
+    let register =
+        r#"{"first_name":"John","last_name":"Doe","email":"j@d.c","password":"12345678"}"#;
+    let login = r#"{"email":"j@d.c","password":"12345678"}"#;
+    let post = |path: &str, body: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(
+                header::COOKIE,
+                format!("{}={}", signed_csrf.name(), signed_csrf.value()),
+            )
+            .header("x-csrf-token", &csrf)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = router
+        .dispatch(post("/register", register), state.clone())
+        .await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let response = router.dispatch(post("/login", login), state.clone()).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str()?;
+    assert!(set_cookie.starts_with("__Host-session_id="));
+    assert!(set_cookie.contains("; Secure"));
+    let pair = set_cookie.split(';').next().unwrap();
+
+    let profile = |cookie: &str| {
+        Request::builder()
+            .uri("/profile/user")
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = router.dispatch(profile(pair), state.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    //The bare token is not enough without its signature
+    let token = pair
+        .strip_prefix("__Host-session_id=")
+        .and_then(|value| value.rsplit_once('.'))
+        .unwrap()
+        .0;
+    let response = router
+        .dispatch(profile(&format!("__Host-session_id={}", token)), state)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use hyper::{Body, Method, Request, StatusCode, header};
+use my_project::{
+    handlers::app_router,
+    structs::app_state::AppState,
+    utils::csrf::{CsrfConsts, CsrfKey, tokens_match},
+};
+
+#[test]
+fn tokens_are_bound_to_key_and_session() {
+    let key = CsrfKey::from_secret("a secret that is long enough to be used");
+    let token = key.issue("session-a");
+    assert!(key.verify(&token, "session-a"));
+    assert!(!key.verify(&token, "session-b"));
+    assert!(!key.verify(&token, ""));
+    assert!(!CsrfKey::random().verify(&token, "session-a"));
+    assert!(!key.verify("not.a-token", "session-a"));
+    assert!(!key.verify("", "session-a"));
+
+    //Same secret, same key, e.g. on another instance
+    let again = CsrfKey::from_secret("a secret that is long enough to be used");
+    assert!(again.verify(&token, "session-a"));
+    assert_ne!(key.issue("session-a"), token);
+
+    assert!(tokens_match(&token, &token.clone()));
+    assert!(!tokens_match(&token, &key.issue("session-a")));
+}
+
+#[tokio::test]
+async fn form_posts_send_the_token_as_a_field() -> Result<()> {
+    let router = app_router();
+    let state = AppState::new_in_memory();
+    let token = state.csrf_key().issue("");
+
+    let post = |body: String, header_token: Option<&str>| {
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri("/login")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::COOKIE, format!("csrf_token={}", token));
+        if let Some(value) = header_token {
+            builder = builder.header("x-csrf-token", value);
+        }
+        builder.body(Body::from(body)).unwrap()
+    };
+
+    let form = format!("email=j%40d.c&password=12345678&csrf_token={}", token);
+    let response = router.dispatch(post(form, None), state.clone()).await;
+    //Past the check, the handler got the body and found no such user
+    assert_ne!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = router
+        .dispatch(post("email=j%40d.c".to_string(), None), state.clone())
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let other = state.csrf_key().issue("");
+    let response = router
+        .dispatch(post(String::new(), Some(&other)), state)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn oversized_forms_are_not_read() -> Result<()> {
+    let router = app_router();
+    let state = AppState::new_in_memory();
+    let token = state.csrf_key().issue("");
+    let form = format!(
+        "csrf_token={}&padding={}",
+        token,
+        "a".repeat(CsrfConsts::MAX_FORM_BYTES)
+    );
+
+    let post = |length: Option<usize>| {
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri("/login")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::COOKIE, format!("csrf_token={}", token));
+        if let Some(length) = length {
+            builder = builder.header(header::CONTENT_LENGTH, length);
+        }
+        builder.body(Body::from(form.clone())).unwrap()
+    };
+
+    //Refused on the declared length, and when it isn't declared, once the limit is passed
+    for length in [Some(form.len()), None] {
+        let response = router.dispatch(post(length), state.clone()).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cookie_only_set_for_pages_that_embed_it() -> Result<()> {
+    let router = app_router();
+    let state = AppState::new_in_memory();
+
+    let get = |path: &str| Request::builder().uri(path).body(Body::empty()).unwrap();
+    let csrf_cookies = |response: &hyper::Response<Body>| {
+        response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter(|value| value.to_str().unwrap().starts_with("csrf_token="))
+            .count()
+    };
+
+    let response = router.dispatch(get("/login"), state.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(csrf_cookies(&response), 1);
+
+    let response = router.dispatch(get("/healthz"), state.clone()).await;
+    assert_eq!(csrf_cookies(&response), 0);
+
+    //A valid cookie is reused, not replaced
+    let token = state.csrf_key().issue("");
+    let request = Request::builder()
+        .uri("/login")
+        .header(header::COOKIE, format!("csrf_token={}", token))
+        .body(Body::empty())?;
+    let response = router.dispatch(request, state).await;
+    assert_eq!(csrf_cookies(&response), 0);
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    assert!(String::from_utf8(body.to_vec())?.contains(&token));
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use hyper::{Body, Request, StatusCode, body::to_bytes, header};
+use my_project::{
+    handlers::app_router,
+    structs::{app_state::AppState, store::SqlStore},
+};
+use serde_json::Value;
+
+async fn probe(state: &AppState, path: &str) -> Result<(StatusCode, Value)> {
+    let request = Request::builder().uri(path).body(Body::empty())?;
+    let response = app_router().dispatch(request, state.clone()).await;
+    let status = response.status();
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+    let body = to_bytes(response.into_body()).await?;
+    Ok((status, serde_json::from_slice(&body)?))
+}
+
+#[tokio::test]
+async fn liveness_and_readiness() -> Result<()> {
+    let state = AppState::new_in_memory();
+
+    let (status, json) = probe(&state, "/healthz").await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["status"], "ok");
+
+    let (status, json) = probe(&state, "/readyz").await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["components"]["store"]["status"], "ok");
+    assert!(json["components"]["store"]["latency_ms"].is_number());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn degraded_when_the_database_is_gone() -> Result<()> {
+    let store = Arc::new(SqlStore::connect("sqlite::memory:").await?);
+    let state = AppState::from_store(store.clone());
+
+    let (status, _) = probe(&state, "/readyz").await?;
+    assert_eq!(status, StatusCode::OK);
+
+    store.pool().close().await;
+    let (status, json) = probe(&state, "/readyz").await?;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["status"], "degraded");
+    assert_eq!(json["components"]["store"]["status"], "down");
+    assert_eq!(json["components"]["store"]["error"], "unreachable");
+
+    //Still alive, only not ready
+    let (status, _) = probe(&state, "/healthz").await?;
+    assert_eq!(status, StatusCode::OK);
+
+    Ok(())
+}
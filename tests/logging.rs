@@ -0,0 +1,160 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use hyper::{Body, Method, Request, StatusCode, header};
+use my_project::{
+    config::{LogConfig, LogFormat, LogLevel},
+    handlers::app_router,
+    logging,
+    middleware::RequestLog,
+    structs::app_state::AppState,
+};
+use serde_json::Value;
+use tracing_subscriber::fmt::MakeWriter;
+
+//Collects everything the subscriber writes
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Capture {
+    type Writer = Capture;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn json_request(state: &AppState, method: Method, path: &str, body: &str) -> Request<Body> {
+    let csrf = state.csrf_key().issue("");
+    Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::COOKIE, format!("csrf_token={}", csrf))
+        .header("x-csrf-token", csrf)
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn secrets_stay_out_of_the_logs() -> Result<()> {
+    let capture = Capture::default();
+    let config = LogConfig {
+        level: LogLevel::Trace,
+        format: LogFormat::Json,
+    };
+    let _guard = tracing::subscriber::set_default(logging::subscriber(&config, capture.clone()));
+
+    let router = app_router();
+    let state = AppState::new_in_memory();
+    let password = "S3cret-pass-word";
+
+    let register = format!(
+        r#"{{"first_name":"John","last_name":"Doe","email":"john@doe.com","password":"{}"}}"#,
+        password
+    );
+    let response = router
+        .dispatch(
+            json_request(&state, Method::POST, "/register", &register),
+            state.clone(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+
+    let login = format!(r#"{{"email":"john@doe.com","password":"{}"}}"#, password);
+    let response = router
+        .dispatch(
+            json_request(&state, Method::POST, "/login", &login),
+            state.clone(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let cookie = response.headers()[header::SET_COOKIE].to_str()?.to_string();
+    let token = cookie
+        .split(';')
+        .next()
+        .and_then(|pair| pair.strip_prefix("session_id="))
+        .unwrap()
+        .to_string();
+
+    let request = Request::builder()
+        .uri("/profile/user?secret=in-the-query")
+        .header(header::COOKIE, format!("session_id={}", token))
+        .body(Body::empty())?;
+    let response = router.dispatch(request, state.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let request_id = response.headers()[RequestLog::REQUEST_ID_HEADER]
+        .to_str()?
+        .to_string();
+
+    //A malformed body with the password in it
+    let broken = format!(r#"{{"email":"john@doe.com","password":{}}}"#, password);
+    router
+        .dispatch(json_request(&state, Method::POST, "/login", &broken), state)
+        .await;
+
+    let logs = capture.text();
+    assert!(!logs.contains(password));
+    assert!(!logs.contains(&token));
+    assert!(!logs.contains("in-the-query"));
+
+    //One JSON object per line, each with the fields of its request span
+    let events: Vec<Value> = logs
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let finished = events
+        .iter()
+        .find(|event| {
+            event["span"]["request_id"] == request_id.as_str()
+                && event["fields"]["message"] == "finished"
+        })
+        .unwrap();
+    assert_eq!(finished["fields"]["status"], 200);
+    assert_eq!(finished["span"]["method"], "GET");
+    assert_eq!(finished["span"]["path"], "/profile/user");
+    assert_eq!(finished["span"]["user_id"], 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn level_filters_our_events() -> Result<()> {
+    let capture = Capture::default();
+    let config = LogConfig {
+        level: LogLevel::Warn,
+        format: LogFormat::Pretty,
+    };
+    let _guard = tracing::subscriber::set_default(logging::subscriber(&config, capture.clone()));
+
+    let request = Request::builder().uri("/nowhere").body(Body::empty())?;
+    let response = app_router()
+        .dispatch(request, AppState::new_in_memory())
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(capture.text().is_empty());
+
+    tracing::warn!("shown");
+    assert!(capture.text().contains("shown"));
+
+    Ok(())
+}
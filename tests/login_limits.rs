@@ -0,0 +1,207 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use hyper::{Body, Method, Request, StatusCode, header};
+use my_project::{
+    handlers::app_router,
+    router::ClientAddr,
+    structs::{
+        AppError,
+        app_state::AppState,
+        login::LoginInfo,
+        throttle::{AttemptKey, LoginAttempts, LoginLimits},
+        user::User,
+    },
+};
+
+fn limits() -> LoginLimits {
+    LoginLimits {
+        max_failures: 3,
+        lockout_secs: 60,
+        address_max_failures: 4,
+        ..LoginLimits::default()
+    }
+}
+
+async fn state_with_user(state: AppState) -> AppState {
+    let user = User::new("John", "Doe", "j@d.c", "12345678").unwrap();
+    state.add_user(user).await.unwrap();
+    state.with_login_limits(limits())
+}
+
+fn login(email: &str, password: &str) -> LoginInfo {
+    LoginInfo::test_new_unchecked(email, password)
+}
+
+#[test]
+fn backoff_doubles_until_the_lockout() {
+    let limits = LoginLimits::default();
+    let key = AttemptKey::Account("j@d.c".to_string());
+    let mut attempts = LoginAttempts::new(&key);
+    let start = SystemTime::now();
+
+    let mut waits = Vec::new();
+    for second in 0..5 {
+        let now = start + Duration::from_secs(second);
+        limits.record_failure(&mut attempts, &key, now);
+        waits.push(attempts.retry_after(now).unwrap_or_default().as_secs());
+    }
+    assert_eq!(waits, [0, 0, 1, 2, 15 * 60]);
+
+    //Forgotten after a quiet window, but not while locked
+    let later = start + Duration::from_secs(4) + limits.window();
+    assert_eq!(attempts.retry_after(later), None);
+    limits.record_failure(&mut attempts, &key, later);
+    assert_eq!(attempts.failures(), 1);
+
+    //Addresses only lock at their own limit, without a backoff
+    let key = AttemptKey::Address(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    let mut attempts = LoginAttempts::new(&key);
+    for _ in 1..limits.address_max_failures {
+        limits.record_failure(&mut attempts, &key, start);
+        assert_eq!(attempts.retry_after(start), None);
+    }
+    limits.record_failure(&mut attempts, &key, start);
+    assert_eq!(attempts.retry_after(start), Some(limits.lockout()));
+
+    //Emails are counted normalized and never stored in the clear
+    assert_eq!(
+        limits.keys(" J@D.c", None),
+        [AttemptKey::Account("j@d.c".to_string())]
+    );
+    assert_ne!(key.hash(), AttemptKey::Account("j@d.c".to_string()).hash());
+}
+
+#[tokio::test]
+async fn locked_accounts_get_429_with_retry_after() -> Result<()> {
+    let router = app_router();
+    let state = state_with_user(AppState::new_in_memory()).await;
+    let csrf = state.csrf_key().issue("");
+
+    let post = |password: &str| {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri("/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::COOKIE, format!("csrf_token={}", csrf))
+            .header("x-csrf-token", &csrf)
+            .body(Body::from(format!(
+                r#"{{"email":"j@d.c","password":"{}"}}"#,
+                password
+            )))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ClientAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        request
+    };
+
+    for _ in 0..3 {
+        let response = router.dispatch(post("wrong-pass"), state.clone()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    //Even the right password, it isn't checked while locked
+    let response = router.dispatch(post("12345678"), state.clone()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[header::RETRY_AFTER].to_str()?.parse()?;
+    assert!((59..=60).contains(&retry_after), "{}", retry_after);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn addresses_are_limited_across_accounts() -> Result<()> {
+    let state = state_with_user(AppState::new_in_memory()).await;
+    let attacker = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+    let other = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+
+    //One guess per account, so no account reaches its limit
+    for n in 0..4 {
+        let email = format!("user{}@d.c", n);
+        let err = state
+            .attempt_login(login(&email, "wrong-pass"), attacker)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Unauthorized(_)));
+    }
+    let err = state
+        .attempt_login(login("j@d.c", "12345678"), attacker)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::TooManyRequests(_)));
+
+    //Everyone else still gets in, and a success clears the account's counter
+    state
+        .attempt_login(login("j@d.c", "wrong-pass"), other)
+        .await
+        .unwrap_err();
+    state
+        .attempt_login(login("j@d.c", "12345678"), other)
+        .await?;
+    for _ in 0..2 {
+        state
+            .attempt_login(login("j@d.c", "wrong-pass"), other)
+            .await
+            .unwrap_err();
+    }
+    state
+        .attempt_login(login("j@d.c", "12345678"), other)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lockout_survives_a_restart() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("my_project_lockout_{}.db", std::process::id()));
+    let db_url = format!("sqlite://{}?mode=rwc", path.display());
+
+    let state = state_with_user(AppState::new(&db_url).await?).await;
+    for _ in 0..3 {
+        state
+            .attempt_login(login("j@d.c", "wrong-pass"), None)
+            .await
+            .unwrap_err();
+    }
+    state.close().await;
+
+    let state = AppState::new(&db_url).await?.with_login_limits(limits());
+    let err = state
+        .attempt_login(login("j@d.c", "12345678"), None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::TooManyRequests(_)));
+
+    //Still blocking, so the reaper keeps it
+    assert_eq!(state.purge_stale_login_attempts().await, 0);
+    state.close().await;
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn forgotten_counters_are_purged() -> Result<()> {
+    let limits = LoginLimits {
+        window_secs: 1,
+        ..limits()
+    };
+    let state = AppState::new("sqlite::memory:")
+        .await?
+        .with_login_limits(limits);
+
+    state
+        .attempt_login(login("j@d.c", "wrong-pass"), None)
+        .await
+        .unwrap_err();
+    assert_eq!(state.purge_stale_login_attempts().await, 0);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(state.purge_stale_login_attempts().await, 1);
+
+    Ok(())
+}
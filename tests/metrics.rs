@@ -0,0 +1,139 @@
+#![cfg(feature = "metrics")]
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use hyper::{Body, Method, Request, StatusCode, body::to_bytes, header};
+use my_project::{
+    config::{Config, StorageBackend},
+    handlers::app_router,
+    metrics::{Metrics, MetricsConsts},
+    router::Router,
+    structs::app_state::AppState,
+};
+
+async fn send(router: &Router, state: &AppState, method: Method, path: &str, body: &str) -> u16 {
+    let csrf = state.csrf_key().issue("");
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::COOKIE, format!("csrf_token={}", csrf))
+        .header("x-csrf-token", csrf)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    router
+        .dispatch(request, state.clone())
+        .await
+        .status()
+        .as_u16()
+}
+
+async fn scrape(router: &Router, state: &AppState) -> Result<(StatusCode, String)> {
+    let request = Request::builder().uri("/metrics").body(Body::empty())?;
+    let response = router.dispatch(request, state.clone()).await;
+    let status = response.status();
+    if status == StatusCode::OK {
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            MetricsConsts::CONTENT_TYPE
+        );
+    }
+    let body = to_bytes(response.into_body()).await?;
+    Ok((status, String::from_utf8(body.to_vec())?))
+}
+
+#[tokio::test]
+async fn counts_requests_and_logins() -> Result<()> {
+    let router = app_router();
+    let state = AppState::new_in_memory().with_metrics(Arc::new(Metrics::new()));
+
+    let register =
+        r#"{"first_name":"John","last_name":"Doe","email":"j@d.com","password":"johnDoe123"}"#;
+    assert_eq!(
+        send(&router, &state, Method::POST, "/register", register).await,
+        302
+    );
+    let good = r#"{"email":"j@d.com","password":"johnDoe123"}"#;
+    assert_eq!(
+        send(&router, &state, Method::POST, "/login", good).await,
+        302
+    );
+    let bad = r#"{"email":"j@d.com","password":"wrongPass123"}"#;
+    assert_eq!(
+        send(&router, &state, Method::POST, "/login", bad).await,
+        401
+    );
+    assert_eq!(
+        send(&router, &state, Method::GET, "/static/a.css", "").await,
+        404
+    );
+    assert_eq!(
+        send(&router, &state, Method::GET, "/nowhere", "").await,
+        404
+    );
+
+    let (status, text) = scrape(&router, &state).await?;
+    assert_eq!(status, StatusCode::OK);
+    let has = |line: &str| text.lines().any(|candidate| candidate == line);
+
+    assert!(has(
+        r#"my_project_http_requests_total{method="POST",route="/login",status="302"} 1"#
+    ));
+    assert!(has(
+        r#"my_project_http_requests_total{method="POST",route="/login",status="401"} 1"#
+    ));
+    //Grouped by pattern, and one series for whatever matched nothing
+    assert!(has(
+        r#"my_project_http_requests_total{method="GET",route="/static/{*path}",status="404"} 1"#
+    ));
+    assert!(has(
+        r#"my_project_http_requests_total{method="GET",route="unmatched",status="404"} 1"#
+    ));
+    assert!(has(
+        r#"my_project_http_request_duration_seconds_count{method="POST",route="/login"} 2"#
+    ));
+    assert!(has(
+        r#"my_project_http_request_duration_seconds_bucket{method="POST",route="/login",le="+Inf"} 2"#
+    ));
+
+    assert!(has(r#"my_project_logins_total{result="success"} 1"#));
+    assert!(has(r#"my_project_logins_total{result="failure"} 1"#));
+    assert!(has("my_project_registrations_total 1"));
+    assert!(has("my_project_active_sessions 1"));
+    assert!(has(
+        r#"my_project_store_operation_duration_seconds_count{operation="insert_session"} 1"#
+    ));
+    //No pool behind the memory store
+    assert!(!text.contains("my_project_db_pool"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pool_gauges_for_sql() -> Result<()> {
+    let mut config = Config::default();
+    config.storage.url = "sqlite::memory:".to_string();
+    config.metrics.enabled = true;
+    let state = AppState::from_config(&config).await?;
+
+    let (status, text) = scrape(&app_router(), &state).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(text.contains("my_project_db_pool_connections{state=\"idle\"}"));
+    assert!(text.contains("my_project_db_pool_max_connections 1"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn disabled_by_default() -> Result<()> {
+    let mut config = Config::default();
+    config.storage.backend = StorageBackend::Memory;
+    let state = AppState::from_config(&config).await?;
+    assert!(state.metrics().is_none());
+
+    let (status, _) = scrape(&app_router(), &state).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    Ok(())
+}
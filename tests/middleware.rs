@@ -0,0 +1,144 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use hyper::{
+    Body, Method, Request, Response, StatusCode,
+    header::{self, HeaderValue},
+};
+use my_project::{
+    middleware::{Middleware, Next, RequireAuth, SetHeaders, Timing},
+    router::{HandlerResult, Router},
+    structs::{app_state::AppState, auth::AuthenticatedUser, login::LoginInfo, user::User},
+};
+
+//Appends its name to the `x-trace` header on the way out
+struct Trace(&'static str);
+
+#[async_trait]
+impl Middleware for Trace {
+    async fn handle(
+        &self,
+        request: Request<Body>,
+        app_state: AppState,
+        next: Next<'_>,
+    ) -> HandlerResult {
+        let mut response = next.run(request, app_state).await?;
+        let trace = match response.headers().get("x-trace") {
+            Some(inner) => format!("{},{}", inner.to_str().unwrap(), self.0),
+            None => self.0.to_string(),
+        };
+        response
+            .headers_mut()
+            .insert("x-trace", HeaderValue::from_str(&trace).unwrap());
+        Ok(response)
+    }
+}
+
+//Answers by itself without calling the handler
+struct Teapot;
+
+#[async_trait]
+impl Middleware for Teapot {
+    async fn handle(&self, _: Request<Body>, _: AppState, _: Next<'_>) -> HandlerResult {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::IM_A_TEAPOT;
+        Ok(response)
+    }
+}
+
+async fn ok(_: Request<Body>, _: AppState) -> HandlerResult {
+    Ok(Response::new(Body::from("ok")))
+}
+
+async fn call(router: &Router, request: Request<Body>, state: &AppState) -> Response<Body> {
+    router.dispatch(request, state.clone()).await
+}
+
+fn get(path: &str) -> Request<Body> {
+    Request::builder().uri(path).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn layers_run_in_order_per_group() -> Result<()> {
+    let state = AppState::new_in_memory();
+
+    let group = Router::new()
+        .get("/inner", ok)
+        .layer(Trace("first"))
+        .layer(Trace("second"));
+    let router = Router::new()
+        .get("/plain", ok)
+        .merge(group)
+        .merge(Router::new().get("/blocked", ok).layer(Teapot))
+        .around(Trace("global"));
+
+    //Inner layers finish first, the global one wraps everything
+    let response = call(&router, get("/inner"), &state).await;
+    assert_eq!(response.headers()["x-trace"], "first,second,global");
+
+    let response = call(&router, get("/plain"), &state).await;
+    assert_eq!(response.headers()["x-trace"], "global");
+
+    let response = call(&router, get("/blocked"), &state).await;
+    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    ////////////////////////////////////////////////////////
+    //Errors become responses before global layers that need one
+    let router = Router::new()
+        .get("/plain", ok)
+        .around(SetHeaders::new().header("x-frame-options", "DENY"))
+        .around(Timing);
+    let response = call(&router, get("/missing"), &state).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()[header::X_FRAME_OPTIONS], "DENY");
+    assert!(response.headers().contains_key("server-timing"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn require_auth() -> Result<()> {
+    let state = AppState::new_in_memory();
+    let user = User::new("John", "Doe", "j@d.c", "12345678").unwrap();
+    state.add_user(user).await.unwrap();
+    let user_id = state
+        .find_user(LoginInfo::new("j@d.c", "12345678").unwrap())
+        .await
+        .unwrap();
+    let token = state.add_session(user_id).await.unwrap();
+
+    let router = Router::new()
+        .route(Method::GET, "/me", |request, _| async move {
+            let id = AuthenticatedUser::of(&request)?.user_id();
+            Ok(Response::new(Body::from(id.to_string())))
+        })
+        .layer(RequireAuth);
+
+    let with_cookie = |token: &str, accept: &str| {
+        Request::builder()
+            .uri("/me")
+            .header(header::COOKIE, format!("session_id={}", token))
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = call(&router, with_cookie(&token, "*/*"), &state).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body, user_id.to_string());
+
+    //Pages are redirected to the login, API calls get a 401, stale cookies are cleared
+    let response = call(&router, with_cookie("forged", "text/html,*/*"), &state).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.headers()[header::LOCATION], "/login?next=%2Fme");
+    assert!(response.headers().contains_key(header::SET_COOKIE));
+
+    let response = call(&router, with_cookie("forged", "*/*"), &state).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers().contains_key(header::SET_COOKIE));
+
+    let response = call(&router, get("/me"), &state).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(!response.headers().contains_key(header::SET_COOKIE));
+
+    Ok(())
+}
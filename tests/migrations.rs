@@ -0,0 +1,111 @@
+use anyhow::Result;
+use my_project::structs::store::{
+    SqlStore,
+    migrations::{self, MigrationError, MigrationStatus},
+    sql::open_pool,
+};
+
+#[tokio::test]
+async fn migrate_up_and_down() -> Result<()> {
+    let (pool, backend) = open_pool("sqlite::memory:").await.unwrap();
+
+    let status = migrations::status(&pool, backend).await.unwrap();
+    assert_eq!(
+        status,
+        MigrationStatus {
+            applied: vec![],
+            pending: vec![1, 2, 3]
+        }
+    );
+
+    assert_eq!(
+        migrations::migrate_up(&pool, backend).await.unwrap(),
+        vec![1, 2, 3]
+    );
+    assert!(
+        migrations::migrate_up(&pool, backend)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        sqlx::query("SELECT id FROM users")
+            .fetch_all(&pool)
+            .await
+            .is_ok()
+    );
+    assert!(
+        sqlx::query("SELECT key_hash FROM login_attempts")
+            .fetch_all(&pool)
+            .await
+            .is_ok()
+    );
+    ////////////////////////////////////////////////////////
+    assert_eq!(
+        migrations::migrate_down(&pool, backend, 1).await.unwrap(),
+        vec![3]
+    );
+    assert!(
+        sqlx::query("SELECT key_hash FROM login_attempts")
+            .fetch_all(&pool)
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        migrations::migrate_down(&pool, backend, 1).await.unwrap(),
+        vec![2]
+    );
+    assert!(
+        sqlx::query("SELECT id FROM users")
+            .fetch_all(&pool)
+            .await
+            .is_ok()
+    );
+    assert_eq!(
+        migrations::migrate_down(&pool, backend, 1).await.unwrap(),
+        vec![1]
+    );
+    assert!(
+        sqlx::query("SELECT id FROM users")
+            .fetch_all(&pool)
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        migrations::status(&pool, backend).await.unwrap().pending,
+        vec![1, 2, 3]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuse_database_ahead_of_binary() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("my_project_ahead_{}.db", std::process::id()));
+    let db_url = format!("sqlite://{}?mode=rwc", path.display());
+
+    let (pool, backend) = open_pool(&db_url).await.unwrap();
+    migrations::migrate_up(&pool, backend).await.unwrap();
+
+    //Pretend a newer release applied a migration we don't ship
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES (9999, 'from the future', TRUE, x'00', 0)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        migrations::ensure_not_ahead(&pool, backend).await,
+        Err(MigrationError::DatabaseAhead {
+            applied: 9999,
+            latest_known: 3
+        })
+    ));
+    pool.close().await;
+
+    assert!(SqlStore::connect(&db_url).await.is_err());
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use anyhow::Result;
+use hyper::{Body, Method, Request, Response, StatusCode, Uri, header};
+use my_project::{
+    handlers::app_router,
+    router::Router,
+    structs::{app_state::AppState, login::LoginInfo, user::User},
+    utils::redirect::{after_login_target, login_redirect_target, safe_next},
+};
+
+async fn call(router: &Router, request: Request<Body>, state: &AppState) -> Response<Body> {
+    router.dispatch(request, state.clone()).await
+}
+
+fn request(method: Method, path: &str, token: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::ACCEPT, "text/html");
+    if let Some(token) = token {
+        builder = builder.header(header::COOKIE, format!("session_id={}", token));
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+fn location(response: &Response<Body>) -> &str {
+    response.headers()[header::LOCATION].to_str().unwrap()
+}
+
+async fn logged_in(state: &AppState) -> String {
+    let user = User::new("John", "Doe", "j@d.c", "12345678").unwrap();
+    state.add_user(user).await.unwrap();
+    let user_id = state
+        .find_user(LoginInfo::new("j@d.c", "12345678").unwrap())
+        .await
+        .unwrap();
+    state.add_session(user_id).await.unwrap()
+}
+
+#[test]
+fn next_targets_stay_same_origin() {
+    assert_eq!(safe_next("/profile?tab=1"), Some("/profile?tab=1"));
+    assert_eq!(safe_next("//evil.com"), None);
+    assert_eq!(safe_next("/\\evil.com"), None);
+    assert_eq!(safe_next("https://evil.com"), None);
+    assert_eq!(safe_next("profile"), None);
+    assert_eq!(safe_next("/home\r\nSet-Cookie: x"), None);
+
+    let uri: Uri = "/login?next=%2Fprofile".parse().unwrap();
+    assert_eq!(after_login_target(&uri), "/profile");
+    let uri: Uri = "/login?next=https%3A%2F%2Fevil.com".parse().unwrap();
+    assert_eq!(after_login_target(&uri), "/home");
+
+    let uri: Uri = "/profile?tab=1".parse().unwrap();
+    assert_eq!(
+        login_redirect_target(&uri),
+        "/login?next=%2Fprofile%3Ftab%3D1"
+    );
+}
+
+#[tokio::test]
+async fn pages_follow_login_state() -> Result<()> {
+    let state = AppState::new_in_memory();
+    let router = app_router();
+
+    //Anonymous visitors only reach the guest pages
+    let response = call(&router, request(Method::GET, "/home", None), &state).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(location(&response), "/login?next=%2Fhome");
+
+    let response = call(&router, request(Method::GET, "/login", None), &state).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    //A forged cookie counts as anonymous and gets cleared
+    let response = call(&router, request(Method::GET, "/profile", Some("x")), &state).await;
+    assert_eq!(location(&response), "/login?next=%2Fprofile");
+    assert!(response.headers().contains_key(header::SET_COOKIE));
+
+    //Logged in users are kept away from the guest pages
+    let token = logged_in(&state).await;
+    let response = call(&router, request(Method::GET, "/home", Some(&token)), &state).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for path in ["/", "/login", "/register"] {
+        let response = call(&router, request(Method::GET, path, Some(&token)), &state).await;
+        assert_eq!(response.status(), StatusCode::FOUND, "{}", path);
+        assert_eq!(location(&response), "/home", "{}", path);
+    }
+    let response = call(
+        &router,
+        request(Method::GET, "/login?next=%2Fprofile", Some(&token)),
+        &state,
+    )
+    .await;
+    assert_eq!(location(&response), "/profile");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_returns_to_next() -> Result<()> {
+    let state = AppState::new_in_memory();
+    let router = app_router();
+    state
+        .add_user(User::new("John", "Doe", "j@d.c", "12345678").unwrap())
+        .await?;
+
+    let csrf = state.csrf_key().issue("");
+    let login = |path: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header(header::COOKIE, format!("csrf_token={}", csrf))
+            .header("x-csrf-token", &csrf)
+            .body(Body::from(r#"{"email":"j@d.c","password":"12345678"}"#))
+            .unwrap()
+    };
+
+    let response = call(&router, login("/login?next=%2Fprofile"), &state).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(location(&response), "/profile");
+    assert!(response.headers().contains_key(header::SET_COOKIE));
+
+    let response = call(&router, login("/login?next=%2F%2Fevil.com"), &state).await;
+    assert_eq!(location(&response), "/home");
+
+    Ok(())
+}
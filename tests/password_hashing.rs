@@ -0,0 +1,91 @@
+use anyhow::Result;
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
+};
+use my_project::structs::{
+    AppError,
+    app_state::AppState,
+    login::LoginInfo,
+    password::{PasswordCheck, exceeds_length_ceiling, hash_password, verify_password},
+    user::{StoredUser, User},
+};
+
+#[tokio::test]
+async fn password_hashing() -> Result<()> {
+    let hash = hash_password("12345678").unwrap();
+
+    assert!(hash.starts_with("$argon2id$v=19$"));
+    assert_ne!(hash, hash_password("12345678").unwrap());
+
+    assert_eq!(verify_password("12345678", &hash), PasswordCheck::Valid);
+    assert_eq!(verify_password("1234567", &hash), PasswordCheck::Invalid);
+    assert_eq!(
+        verify_password("12345678", "12345678"),
+        PasswordCheck::Invalid
+    );
+    ////////////////////////////////////////////////////////
+    //Hash made with weaker parameters should be upgraded on a successful login
+    let weak = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(8 * 1024, 1, 1, None).unwrap(),
+    )
+    .hash_password(b"12345678", &SaltString::generate(&mut OsRng))
+    .unwrap()
+    .to_string();
+
+    let PasswordCheck::ValidNeedsRehash(new_hash) = verify_password("12345678", &weak) else {
+        panic!("Expected the weak hash to need a rehash");
+    };
+    assert_eq!(verify_password("12345678", &new_hash), PasswordCheck::Valid);
+    assert_eq!(verify_password("wrong-pass", &weak), PasswordCheck::Invalid);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stored_user_keeps_only_hash() -> Result<()> {
+    let user = User::new("Joan", "Doan", "j@d.c", "12345678").unwrap();
+    let mut stored_user = StoredUser::new(1, user.clone()).unwrap();
+
+    assert_ne!(stored_user.password_hash(), "12345678");
+    assert!(!format!("{}", stored_user).contains("12345678"));
+    assert!(!format!("{}", user).contains("12345678"));
+    assert!(!format!("{:?}", user).contains("12345678"));
+
+    let login = LoginInfo::test_new_unchecked("j@d.c", "12345678");
+    assert!(stored_user.verify_credentials(&login).await.is_valid());
+    let login = LoginInfo::test_new_unchecked("j@d.c", "87654321");
+    assert!(!stored_user.verify_credentials(&login).await.is_valid());
+    ////////////////////////////////////////////////////////
+    let updated = User::new("Joan", "Doan", "j@d.c", "87654321").unwrap();
+    stored_user.update(&updated).await.unwrap();
+    assert!(stored_user.verify_credentials(&login).await.is_valid());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn huge_login_passwords_are_not_hashed() -> Result<()> {
+    let state = AppState::new_in_memory();
+    let user = User::new("Joan", "Doan", "j@d.c", "12345678").unwrap();
+    state.add_user(user).await?;
+
+    //Past the ceiling of every password policy, known and unknown emails alike
+    let huge = "1".repeat(4 * 1024 * 1024);
+    for email in ["j@d.c", "nobody@d.c"] {
+        let login = LoginInfo::new(email, &huge).unwrap();
+        assert!(matches!(
+            state.find_user(login).await,
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+    assert!(exceeds_length_ceiling(&"1".repeat(1025)));
+    assert!(!exceeds_length_ceiling(&"1".repeat(1024)));
+
+    let login = LoginInfo::new("j@d.c", "12345678").unwrap();
+    assert!(state.find_user(login).await.is_ok());
+
+    Ok(())
+}
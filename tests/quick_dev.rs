@@ -1,22 +1,52 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
 use anyhow::{Context, Result};
 use hyper::{
-    Body, Client, Method, Request, Response, Uri,
+    Body, Client, HeaderMap, Method, Request, Response, Server, StatusCode, Uri,
     body::to_bytes,
     client::HttpConnector,
-    header::{CONTENT_TYPE, COOKIE, HeaderValue},
+    header::{CONTENT_TYPE, COOKIE, HeaderValue, SET_COOKIE},
+    service::{make_service_fn, service_fn},
 };
+use my_project::{handlers::app_router, structs::app_state::AppState};
+
+//The whole server on an ephemeral port, backed by an in-memory store
+async fn spawn_server() -> SocketAddr {
+    let app_state = AppState::new_in_memory();
+    let router = Arc::new(app_router());
 
+    let make_service = make_service_fn(move |_socket| {
+        let app_state = app_state.clone();
+        let router = router.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let app_state = app_state.clone();
+                let router = router.clone();
+                async move { Ok::<_, Infallible>(router.dispatch(request, app_state).await) }
+            }))
+        }
+    });
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
 
 #[tokio::test]
 async fn quick_dev() -> Result<()> {
+    let addr = spawn_server().await;
     let client = Client::new();
 
     ////////////////////////////////////////
-    //Get request for the home page
-    // send_get("/home", &client).await?;
+    //Get request for the login page, it hands out the CSRF token
+    let (status, headers, body) = send_get(addr, "/login", &client).await?;
+    assert_eq!(status, StatusCode::OK);
+    let csrf = csrf_from(&headers).context("No CSRF cookie")?;
+    assert!(body.contains(&csrf));
 
     ////////////////////////////////////////
-    //Post request for the home page
+    //Post request for registering
     let data = r#"
         {
             "first_name": "John",
@@ -25,11 +55,43 @@ async fn quick_dev() -> Result<()> {
             "password": "johnDoe123"
         }"#;
 
-    send_post("/home", &client, data, None).await?;
-    // send_post("/home", &client, data).await?;
+    let (status, _, _) = send_post(addr, "/register", &client, data, None, None).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send_post(addr, "/register", &client, data, None, Some(&csrf)).await?;
+    assert_eq!(status, StatusCode::FOUND);
+    let (status, _, _) = send_post(addr, "/register", &client, data, None, Some(&csrf)).await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    ////////////////////////////////////////
+    //Send post request for login
+    let login_data = r#"
+    {
+        "email": "john@doe.com",
+        "password": "johnDoe123"
+    }"#;
+    let (status, headers, _) =
+        send_post(addr, "/login", &client, login_data, None, Some(&csrf)).await?;
+    assert_eq!(status, StatusCode::FOUND);
+    let session_id = session_id_from(&headers).context("No session cookie")?;
+
+    ////////////////////////////////////////
+    //The token from before the login is no good for the session
+    let (status, _, _) =
+        send_delete(addr, "/logout", &client, Some(&session_id), Some(&csrf)).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, headers, _) = send_get_with_cookie(addr, "/home", &client, &session_id).await?;
+    assert_eq!(status, StatusCode::OK);
+    let csrf = csrf_from(&headers).context("No CSRF cookie for the session")?;
 
     ////////////////////////////////////////
-    //Put request for the home page
+    //Checking if the cookie works
+    let (status, _, body) =
+        send_get_with_cookie(addr, "/profile/user", &client, &session_id).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("john@doe.com"));
+
+    ////////////////////////////////////////
+    //Put request for the profile page
     let put_data = r#"
         {
             "first_name": "Johny",
@@ -38,58 +100,99 @@ async fn quick_dev() -> Result<()> {
             "password": "johnDoe12345"
         }"#;
 
-    send_put("/home/0", &client, put_data).await?;
-
-    ////////////////////////////////////////
-    //Send post request for login
-    // let login_data = r#"
-    // {
-    //     "email": "john@doe.com",
-    //     "password": "johnDoe123"
-    // }"#;
-    // send_post("/login", &client, login_data, None).await?;
+    let (status, _, _) = send_put(addr, "/profile", &client, put_data, &session_id, &csrf).await?;
+    assert_eq!(status, StatusCode::FOUND);
 
     ////////////////////////////////////////
-    //Checking if the cookie works
-    // send_post("/login", &client, login_data, Some("0")).await?;
+    //Sending delete request
+    let (status, _, _) =
+        send_delete(addr, "/logout", &client, Some(&session_id), Some(&csrf)).await?;
+    assert_eq!(status, StatusCode::FOUND);
 
     ////////////////////////////////////////
-    //Sending delete request
-    // send_delete("/logout", &client, Some("0")).await?;
+    //Unknown paths and wrong methods
+    let (status, _, _) = send_get(addr, "/nope", &client).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, headers, _) = send_delete(addr, "/register", &client, None, None).await?;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(headers["allow"], "GET, POST, HEAD, OPTIONS");
 
     Ok(())
 }
+
+fn session_id_from(headers: &HeaderMap) -> Option<String> {
+    let cookie = headers.get(SET_COOKIE)?.to_str().ok()?;
+    let value = cookie.strip_prefix("session_id=")?;
+    value.split(';').next().map(String::from)
+}
+
+fn csrf_from(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|cookie| cookie.to_str().ok()?.strip_prefix("csrf_token="))
+        .find_map(|value| value.split(';').next().map(String::from))
+}
+
+//What a browser sends back: the cookies, and the page's CSRF token in the header
+fn with_cookies(
+    builder: hyper::http::request::Builder,
+    session_id: Option<&str>,
+    csrf: Option<&str>,
+) -> hyper::http::request::Builder {
+    let mut cookies = Vec::new();
+    if let Some(id) = session_id {
+        cookies.push(format!("session_id={}", id));
+    }
+    if let Some(token) = csrf {
+        cookies.push(format!("csrf_token={}", token));
+    }
+    let cookie = match cookies.is_empty() {
+        true => HeaderValue::from_str("No cookies here").unwrap(),
+        false => HeaderValue::from_str(&cookies.join("; ")).unwrap(),
+    };
+    let builder = builder.header(COOKIE, cookie);
+    match csrf {
+        Some(token) => builder.header("x-csrf-token", token),
+        None => builder,
+    }
+}
+
+type Report = (StatusCode, HeaderMap, String);
+
 async fn send_delete(
+    addr: SocketAddr,
     path: &str,
     client: &Client<HttpConnector>,
     session_id: Option<&str>,
-) -> Result<()> {
-    let uri = make_uri(path)?;
+    csrf: Option<&str>,
+) -> Result<Report> {
+    let uri = make_uri(addr, path)?;
 
-    //Adding the optional session_id
-    let cookie = match session_id {
-        Some(id) => HeaderValue::from_str(&format!("session_id={};", id)).unwrap(),
-        None => HeaderValue::from_str("No cookies here").unwrap(),
-    };
-
-    let req = Request::builder()
+    let req = with_cookies(Request::builder(), session_id, csrf)
         .method(&Method::DELETE)
         .uri(uri)
-        .header(COOKIE, cookie)
         .body(Body::from("Loggin out"))
         .unwrap();
 
     let resp = client
         .request(req)
         .await
-        .context("Failed to send POST request")?;
+        .context("Failed to send DELETE request")?;
 
     parse_response(resp).await
 }
 
-async fn send_put(path: &str, client: &Client<HttpConnector>, data: &str) -> Result<()> {
-    let uri = make_uri(path)?;
-    let req = Request::builder()
+async fn send_put(
+    addr: SocketAddr,
+    path: &str,
+    client: &Client<HttpConnector>,
+    data: &str,
+    session_id: &str,
+    csrf: &str,
+) -> Result<Report> {
+    let uri = make_uri(addr, path)?;
+    let req = with_cookies(Request::builder(), Some(session_id), Some(csrf))
         .method(&Method::PUT)
         .uri(uri)
         .header(CONTENT_TYPE, "application/json")
@@ -105,24 +208,19 @@ async fn send_put(path: &str, client: &Client<HttpConnector>, data: &str) -> Res
 }
 
 async fn send_post(
+    addr: SocketAddr,
     path: &str,
     client: &Client<HttpConnector>,
     data: &str,
     session_id: Option<&str>,
-) -> Result<()> {
-    let uri = make_uri(path)?;
+    csrf: Option<&str>,
+) -> Result<Report> {
+    let uri = make_uri(addr, path)?;
 
-    //Adding the optional session_id
-    let cookie = match session_id {
-        Some(id) => HeaderValue::from_str(&format!("session_id={};", id)).unwrap(),
-        None => HeaderValue::from_str("No cookies here").unwrap(),
-    };
-
-    let req = Request::builder()
+    let req = with_cookies(Request::builder(), session_id, csrf)
         .method(&Method::POST)
         .uri(uri)
         .header(CONTENT_TYPE, "application/json")
-        .header(COOKIE, cookie)
         .body(Body::from(data.to_owned()))
         .unwrap();
 
@@ -134,9 +232,8 @@ async fn send_post(
     parse_response(resp).await
 }
 
-async fn send_get(path: &str, client: &Client<HttpConnector>) -> Result<()> {
-    //Get request for the home page
-    let uri = make_uri(path)?;
+async fn send_get(addr: SocketAddr, path: &str, client: &Client<HttpConnector>) -> Result<Report> {
+    let uri = make_uri(addr, path)?;
     let resp = client
         .get(uri)
         .await
@@ -145,13 +242,32 @@ async fn send_get(path: &str, client: &Client<HttpConnector>) -> Result<()> {
     parse_response(resp).await
 }
 
-fn make_uri(path: &str) -> Result<Uri, anyhow::Error> {
-    let base_path = "http://127.0.0.1:3000";
-    let whole_path = format!("{}{}", base_path, path);
+async fn send_get_with_cookie(
+    addr: SocketAddr,
+    path: &str,
+    client: &Client<HttpConnector>,
+    session_id: &str,
+) -> Result<Report> {
+    let req = Request::builder()
+        .uri(make_uri(addr, path)?)
+        .header(COOKIE, format!("session_id={}", session_id))
+        .body(Body::empty())
+        .unwrap();
+
+    let resp = client
+        .request(req)
+        .await
+        .context("Failed to send GET request")?;
+
+    parse_response(resp).await
+}
+
+fn make_uri(addr: SocketAddr, path: &str) -> Result<Uri, anyhow::Error> {
+    let whole_path = format!("http://{}{}", addr, path);
     Ok(whole_path.parse::<Uri>()?)
 }
 
-async fn parse_response(response: Response<Body>) -> Result<()> {
+async fn parse_response(response: Response<Body>) -> Result<Report> {
     let (parts, body) = response.into_parts();
 
     let resp_statuc = parts.status;
@@ -159,7 +275,7 @@ async fn parse_response(response: Response<Body>) -> Result<()> {
     let resp_body = to_bytes(body)
         .await
         .context("Failed to read response body")?;
-    let body_to_str = String::from_utf8_lossy(&resp_body);
+    let body_to_str = String::from_utf8_lossy(&resp_body).to_string();
 
     println!("=== HTTP Response Report ===");
     println!("Statuc code {}", resp_statuc);
@@ -170,5 +286,5 @@ async fn parse_response(response: Response<Body>) -> Result<()> {
     println!("===Response body=== \n");
     println!("{}", &body_to_str);
 
-    Ok(())
+    Ok((resp_statuc, resp_header, body_to_str))
 }
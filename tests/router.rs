@@ -0,0 +1,109 @@
+use anyhow::Result;
+use hyper::{Body, Method, Request, Response, StatusCode, body::to_bytes, header};
+use my_project::{
+    router::{MatchedPath, PathParams, Router},
+    structs::{AppError, app_state::AppState},
+};
+
+fn text(body: String) -> Result<Response<Body>, AppError> {
+    Ok(Response::new(Body::from(body)))
+}
+
+fn test_router() -> Router {
+    Router::new()
+        .get("/users/{id}", |request, _| async move {
+            let id: usize = PathParams::of(&request).unwrap().parse("id")?;
+            text(format!("user {}", id))
+        })
+        .get("/users/me", |_, _| async { text("me".to_string()) })
+        .delete("/users/{id}", |_, _| async { text("deleted".to_string()) })
+        .get("/files/{*path}", |request, _| async move {
+            let path = PathParams::of(&request).unwrap().get("path").unwrap_or("");
+            text(format!("file {}", path))
+        })
+}
+
+async fn call(router: &Router, method: Method, path: &str) -> (StatusCode, Response<Body>) {
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.dispatch(request, AppState::new_in_memory()).await;
+    (response.status(), response)
+}
+
+async fn body_of(response: Response<Body>) -> String {
+    let bytes = to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+#[tokio::test]
+async fn path_params_and_wildcards() -> Result<()> {
+    let router = test_router();
+
+    let (status, response) = call(&router, Method::GET, "/users/42").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body_of(response).await, "user 42");
+
+    //Literal segments beat parameters regardless of registration order
+    let (_, response) = call(&router, Method::GET, "/users/me/").await;
+    assert_eq!(body_of(response).await, "me");
+
+    let (status, _) = call(&router, Method::GET, "/users/abc").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, response) = call(&router, Method::GET, "/files/css/site.css").await;
+    assert_eq!(body_of(response).await, "file css/site.css");
+    let (_, response) = call(&router, Method::GET, "/files").await;
+    assert_eq!(body_of(response).await, "file ");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn not_found_and_method_handling() -> Result<()> {
+    let router = test_router();
+
+    let (status, _) = call(&router, Method::GET, "/nope").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call(&router, Method::GET, "/users/1/extra").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, response) = call(&router, Method::POST, "/users/1").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        response.headers()[header::ALLOW],
+        "GET, DELETE, HEAD, OPTIONS"
+    );
+    ////////////////////////////////////////////////////////
+    let (status, response) = call(&router, Method::HEAD, "/users/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body_of(response).await.is_empty());
+
+    let (status, response) = call(&router, Method::OPTIONS, "/users/me").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn matched_path_on_responses() -> Result<()> {
+    let router = test_router();
+    let matched =
+        |response: &Response<Body>| MatchedPath::of(response).map(|m| m.as_str().to_string());
+
+    let (_, response) = call(&router, Method::GET, "/users/7").await;
+    assert_eq!(matched(&response).as_deref(), Some("/users/{id}"));
+    let (_, response) = call(&router, Method::GET, "/files/css/site.css").await;
+    assert_eq!(matched(&response).as_deref(), Some("/files/{*path}"));
+    //Also on answers the router gives itself
+    let (status, response) = call(&router, Method::PUT, "/users/7").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(matched(&response).as_deref(), Some("/users/{id}"));
+    let (_, response) = call(&router, Method::GET, "/nowhere").await;
+    assert_eq!(matched(&response), None);
+
+    Ok(())
+}
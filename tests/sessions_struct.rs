@@ -1,9 +1,72 @@
 use anyhow::Result;
-use my_project::structs::session::Session;
+use std::time::Duration;
+
+use my_project::structs::{
+    app_state::AppState,
+    session::{Session, SessionConfig},
+    user::User,
+};
 
 #[tokio::test]
 async fn sessions_testing() -> Result<()> {
-    assert!(Session::new("1".to_string(), 1).is_ok());
-    assert!(Session::new("".to_string(), 1).is_err());
+    let (session, token) = Session::new(1).unwrap();
+    let (other_session, other_token) = Session::new(1).unwrap();
+
+    //32 random bytes, url safe base64 without padding
+    assert_eq!(token.len(), 43);
+    assert!(
+        token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    );
+    assert_ne!(token, other_token);
+
+    //Only the hash of the token is kept in the session
+    assert_eq!(session.token_hash(), &Session::hash_token(&token));
+    assert_ne!(session.token_hash(), other_session.token_hash());
+    assert!(!format!("{}", session).contains(&token));
+    assert_eq!(*session.user_id(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn session_expiry() -> Result<()> {
+    let config = SessionConfig::new(Duration::from_secs(60), Duration::from_secs(10));
+    let (mut session, _token) = Session::new(1).unwrap();
+    let start = session.created_at();
+
+    assert!(!session.is_expired(&config, start));
+    assert!(session.is_expired(&config, start + Duration::from_secs(10)));
+
+    //Sliding renewal moves the idle deadline
+    session.touch(start + Duration::from_secs(8));
+    assert!(!session.is_expired(&config, start + Duration::from_secs(15)));
+
+    //...but never past the absolute lifetime
+    session.touch(start + Duration::from_secs(55));
+    assert_eq!(session.expires_at(&config), start + Duration::from_secs(60));
+    assert!(session.is_expired(&config, start + Duration::from_secs(60)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn session_reaper() -> Result<()> {
+    let config = SessionConfig::new(Duration::from_secs(60), Duration::from_millis(50));
+    let state = AppState::new_in_memory().with_session_config(config);
+    //Sessions only count while their user exists
+    let user = User::new("John", "Doe", "j@d.c", "12345678").unwrap();
+    state.add_user(user).await.unwrap();
+
+    let token = state.add_session(0).await.unwrap();
+    state.add_session(0).await.unwrap();
+    assert!(state.is_session_valid(&token).await);
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert!(!state.is_session_valid(&token).await);
+
+    let reaper = state.spawn_session_reaper(Duration::from_millis(10));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(state.print_session_count().await, 0);
+    reaper.abort();
     Ok(())
 }
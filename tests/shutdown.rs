@@ -0,0 +1,166 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use hyper::{
+    Body, Client, Request, Response, Server, StatusCode,
+    server::conn::AddrIncoming,
+    service::{make_service_fn, service_fn},
+};
+use my_project::{
+    router::Router,
+    shutdown::{ExitCodes, Outcome, ShutdownReason, serve_until},
+    structs::{app_state::AppState, store::SqlStore},
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+//Answers /slow after `delay`, holding a pool connection meanwhile when the store has one.
+//The signal is sent by the test through the returned sender
+fn spawn_server(
+    app_state: AppState,
+    delay: Duration,
+    deadline: Duration,
+) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<Outcome>) {
+    let router = Arc::new(
+        Router::new().get("/slow", move |_request, app_state| async move {
+            let _connection = match app_state.store().sql_pool() {
+                Some(pool) => Some(pool.acquire().await?),
+                None => None,
+            };
+            tokio::time::sleep(delay).await;
+            Ok(Response::new(Body::from("done")))
+        }),
+    );
+
+    let make_service = make_service_fn(move |_socket| {
+        let app_state = app_state.clone();
+        let router = router.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let app_state = app_state.clone();
+                let router = router.clone();
+                async move { Ok::<_, Infallible>(router.dispatch(request, app_state).await) }
+            }))
+        }
+    });
+
+    let incoming = AddrIncoming::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+    let addr = incoming.local_addr();
+    let (signal, received) = oneshot::channel::<()>();
+
+    let outcome = tokio::spawn(serve_until(
+        move |stop| {
+            Server::builder(incoming)
+                .serve(make_service)
+                .with_graceful_shutdown(stop)
+        },
+        async move {
+            let _ = received.await;
+            ShutdownReason::Terminate
+        },
+        deadline,
+    ));
+    (addr, signal, outcome)
+}
+
+#[tokio::test]
+async fn in_flight_request_finishes() -> Result<()> {
+    let (addr, signal, outcome) = spawn_server(
+        AppState::new_in_memory(),
+        Duration::from_millis(300),
+        Duration::from_secs(5),
+    );
+
+    let request = tokio::spawn(async move {
+        let uri = format!("http://{}/slow", addr).parse().unwrap();
+        Client::new().get(uri).await
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    signal.send(()).unwrap();
+
+    //The request started before the signal still gets its answer
+    let response = request.await??;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(hyper::body::to_bytes(response.into_body()).await?, "done");
+
+    let outcome = outcome.await?;
+    assert!(matches!(outcome, Outcome::Drained));
+    assert_eq!(outcome.exit_code(), ExitCodes::OK);
+
+    //And nobody new gets in
+    let uri = format!("http://{}/slow", addr).parse().unwrap();
+    assert!(Client::new().get(uri).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn deadline_stops_waiting() -> Result<()> {
+    let (addr, signal, outcome) = spawn_server(
+        AppState::new_in_memory(),
+        Duration::from_secs(30),
+        Duration::from_millis(100),
+    );
+
+    let request = tokio::spawn(async move {
+        let uri = format!("http://{}/slow", addr).parse().unwrap();
+        Client::new().get(uri).await
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    signal.send(()).unwrap();
+
+    let outcome = tokio::time::timeout(Duration::from_secs(5), outcome).await??;
+    assert!(matches!(outcome, Outcome::DeadlineExceeded));
+    assert_eq!(outcome.exit_code(), ExitCodes::DRAIN_TIMEOUT);
+    //Still waiting, the process exit is what ends it
+    assert!(!request.is_finished());
+    request.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn close_releases_the_pool() -> Result<()> {
+    let store = Arc::new(SqlStore::connect("sqlite::memory:").await?);
+    let app_state = AppState::from_store(store.clone());
+
+    app_state.close().await;
+    assert!(store.pool().is_closed());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stuck_request_does_not_hold_up_the_exit() -> Result<()> {
+    let store = Arc::new(SqlStore::connect("sqlite::memory:").await?);
+    let app_state = AppState::from_store(store.clone());
+    let deadline = Duration::from_millis(200);
+    let (addr, signal, outcome) =
+        spawn_server(app_state.clone(), Duration::from_secs(30), deadline);
+
+    let request = tokio::spawn(async move {
+        let uri = format!("http://{}/slow", addr).parse().unwrap();
+        Client::new().get(uri).await
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    signal.send(()).unwrap();
+    let started = Instant::now();
+
+    //The request keeps the only connection of the pool, closing can't wait for it
+    let outcome = outcome.await?;
+    assert!(matches!(outcome, Outcome::DeadlineExceeded));
+    assert_eq!((store.pool().size(), store.pool().num_idle()), (1, 0));
+    let closed = app_state
+        .close_within(outcome.close_timeout(deadline))
+        .await;
+    assert!(!closed);
+    assert!(store.pool().is_closed());
+    assert!(started.elapsed() < deadline * 5, "{:?}", started.elapsed());
+    request.abort();
+
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use hyper::{Body, Method, Request, Response, StatusCode, header};
+use my_project::{
+    handlers::app_router,
+    structs::app_state::AppState,
+    utils::static_files::{StaticFiles, mime_type, parse_range, resolve},
+};
+
+//A fresh directory per test, removed up front so reruns start clean
+fn asset_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my_project_static_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("public/css")).unwrap();
+    fs::write(dir.join("public/css/site.css"), "body { color: red; }").unwrap();
+    fs::write(dir.join("public/.env"), "SECRET=1").unwrap();
+    fs::write(dir.join("secret.txt"), "outside the root").unwrap();
+    dir
+}
+
+fn get(headers: &[(header::HeaderName, &str)]) -> Request<Body> {
+    let mut builder = Request::builder();
+    for (name, value) in headers {
+        builder = builder.header(name, *value);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+async fn body_of(response: Response<Body>) -> String {
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn paths_stay_under_root() {
+    let root = PathBuf::from("/srv");
+    assert_eq!(
+        resolve(&root, "css/site.css"),
+        Some(PathBuf::from("/srv/css/site.css"))
+    );
+    assert_eq!(
+        resolve(&root, "my%20file.txt"),
+        Some(PathBuf::from("/srv/my file.txt"))
+    );
+    for path in [
+        "",
+        "../etc/passwd",
+        "css/../../etc/passwd",
+        "%2e%2e/etc/passwd",
+        "css%2F..%2F..%2Fsecret",
+        "..%5Csecret",
+        ".env",
+        "css//site.css",
+        "C:%5Cwindows",
+    ] {
+        assert_eq!(resolve(&root, path), None, "{}", path);
+    }
+}
+
+#[test]
+fn content_types_and_ranges() {
+    assert_eq!(mime_type("a/site.CSS".as_ref()), "text/css; charset=utf-8");
+    assert_eq!(mime_type("logo.svg".as_ref()), "image/svg+xml");
+    assert_eq!(mime_type("app.wasm".as_ref()), "application/wasm");
+    assert_eq!(mime_type("blob".as_ref()), "application/octet-stream");
+
+    assert_eq!(parse_range("bytes=0-4", 10), Some(Ok((0, 4))));
+    assert_eq!(parse_range("bytes=5-", 10), Some(Ok((5, 9))));
+    assert_eq!(parse_range("bytes=-3", 10), Some(Ok((7, 9))));
+    assert_eq!(parse_range("bytes=8-100", 10), Some(Ok((8, 9))));
+    assert_eq!(parse_range("bytes=10-", 10), Some(Err(())));
+    assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+    assert_eq!(parse_range("bytes=5-1", 10), None);
+    assert_eq!(parse_range("items=0-1", 10), None);
+}
+
+#[tokio::test]
+async fn serves_files_with_validators() -> Result<()> {
+    let dir = asset_dir("validators");
+    let files = StaticFiles::new(dir.join("public"));
+
+    let response = files.serve(&get(&[]), "css/site.css").await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/css; charset=utf-8"
+    );
+    assert!(response.headers().contains_key(header::LAST_MODIFIED));
+    let etag = response.headers()[header::ETAG].to_str()?.to_string();
+    let last_modified = response.headers()[header::LAST_MODIFIED]
+        .to_str()?
+        .to_string();
+    assert_eq!(body_of(response).await, "body { color: red; }");
+
+    let response = files
+        .serve(&get(&[(header::IF_NONE_MATCH, &etag)]), "css/site.css")
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(body_of(response).await, "");
+
+    let response = files
+        .serve(
+            &get(&[(header::IF_MODIFIED_SINCE, &last_modified)]),
+            "css/site.css",
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = files
+        .serve(
+            &get(&[(header::IF_NONE_MATCH, "\"other\"")]),
+            "css/site.css",
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    //Dot files, directories and anything outside the root are not there
+    for path in [
+        ".env",
+        "css",
+        "../secret.txt",
+        "%2e%2e/secret.txt",
+        "nope.css",
+    ] {
+        let err = files.serve(&get(&[]), path).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ranges_and_precompressed() -> Result<()> {
+    let dir = asset_dir("ranges");
+    let files = StaticFiles::new(dir.join("public"));
+
+    let response = files
+        .serve(&get(&[(header::RANGE, "bytes=0-3")]), "css/site.css")
+        .await?;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 0-3/20");
+    assert_eq!(body_of(response).await, "body");
+
+    let response = files
+        .serve(&get(&[(header::RANGE, "bytes=50-")]), "css/site.css")
+        .await?;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */20");
+
+    //A stale If-Range gets the whole file
+    let response = files
+        .serve(
+            &get(&[(header::RANGE, "bytes=0-3"), (header::IF_RANGE, "\"old\"")]),
+            "css/site.css",
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    ////////////////////////////////////////////////////////
+    fs::write(dir.join("public/css/site.css.br"), "brotli bytes")?;
+    fs::write(dir.join("public/css/site.css.gz"), "gzip bytes")?;
+
+    let response = files
+        .serve(
+            &get(&[(header::ACCEPT_ENCODING, "gzip, br")]),
+            "css/site.css",
+        )
+        .await?;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/css; charset=utf-8"
+    );
+    assert_eq!(response.headers()[header::VARY], "Accept-Encoding");
+    assert_eq!(body_of(response).await, "brotli bytes");
+
+    let response = files
+        .serve(
+            &get(&[(header::ACCEPT_ENCODING, "gzip;q=1, br;q=0")]),
+            "css/site.css",
+        )
+        .await?;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let response = files.serve(&get(&[]), "css/site.css").await?;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn static_route() -> Result<()> {
+    let router = app_router();
+    let state = AppState::new_in_memory();
+
+    let request = Request::builder()
+        .uri("/static/loginPageStyle.css")
+        .body(Body::empty())?;
+    let response = router.dispatch(request, state.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::builder()
+        .method(Method::HEAD)
+        .uri("/static/loginPageStyle.css")
+        .body(Body::empty())?;
+    let response = router.dispatch(request, state.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_of(response).await, "");
+
+    let request = Request::builder()
+        .uri("/static/%2e%2e/layout.html")
+        .body(Body::empty())?;
+    let response = router.dispatch(request, state).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
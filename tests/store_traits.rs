@@ -0,0 +1,177 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use my_project::structs::{
+    AppError,
+    app_state::AppState,
+    login::LoginInfo,
+    session::{Session, SessionConfig, SessionTokenHash},
+    store::{LoginAttemptStore, MemoryStore, SessionStore, Store, UserStore},
+    throttle::{AttemptKeyHash, LoginAttempts},
+    user::{StoredUser, User},
+};
+
+//Counts user lookups and refuses to create sessions
+struct FakeStore {
+    inner: MemoryStore,
+    lookups: AtomicUsize,
+}
+
+#[async_trait]
+impl UserStore for FakeStore {
+    async fn insert_user(&self, user: &User, password_hash: &str) -> Result<StoredUser, AppError> {
+        self.inner.insert_user(user, password_hash).await
+    }
+    async fn update_user(&self, user: &StoredUser) -> Result<(), AppError> {
+        self.inner.update_user(user).await
+    }
+    async fn find_user_by_id(&self, user_id: usize) -> Result<Option<StoredUser>, AppError> {
+        self.inner.find_user_by_id(user_id).await
+    }
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<StoredUser>, AppError> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        self.inner.find_user_by_email(email).await
+    }
+    async fn user_count(&self) -> Result<usize, AppError> {
+        self.inner.user_count().await
+    }
+    async fn all_users(&self) -> Result<Vec<StoredUser>, AppError> {
+        self.inner.all_users().await
+    }
+}
+
+#[async_trait]
+impl SessionStore for FakeStore {
+    async fn insert_session(&self, _session: &Session) -> Result<(), AppError> {
+        Err(AppError::Internal("sessions are disabled".to_string()))
+    }
+    async fn find_session(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<Session>, AppError> {
+        self.inner.find_session(token_hash).await
+    }
+    async fn find_session_with_user(
+        &self,
+        token_hash: &SessionTokenHash,
+    ) -> Result<Option<(Session, StoredUser)>, AppError> {
+        self.inner.find_session_with_user(token_hash).await
+    }
+    async fn update_session(&self, session: &Session) -> Result<(), AppError> {
+        self.inner.update_session(session).await
+    }
+    async fn delete_session(&self, token_hash: &SessionTokenHash) -> Result<(), AppError> {
+        self.inner.delete_session(token_hash).await
+    }
+    async fn delete_expired_sessions(
+        &self,
+        config: &SessionConfig,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        self.inner.delete_expired_sessions(config, now).await
+    }
+    async fn session_count(&self) -> Result<usize, AppError> {
+        self.inner.session_count().await
+    }
+    async fn all_sessions(&self) -> Result<Vec<Session>, AppError> {
+        self.inner.all_sessions().await
+    }
+}
+
+//The default close, nothing to release
+#[async_trait]
+impl LoginAttemptStore for FakeStore {
+    async fn find_login_attempts(
+        &self,
+        key_hash: &AttemptKeyHash,
+    ) -> Result<Option<LoginAttempts>, AppError> {
+        self.inner.find_login_attempts(key_hash).await
+    }
+    async fn save_login_attempts(&self, attempts: &LoginAttempts) -> Result<(), AppError> {
+        self.inner.save_login_attempts(attempts).await
+    }
+    async fn delete_login_attempts(&self, key_hash: &AttemptKeyHash) -> Result<(), AppError> {
+        self.inner.delete_login_attempts(key_hash).await
+    }
+    async fn delete_stale_login_attempts(
+        &self,
+        failed_before: SystemTime,
+        now: SystemTime,
+    ) -> Result<usize, AppError> {
+        self.inner
+            .delete_stale_login_attempts(failed_before, now)
+            .await
+    }
+}
+
+impl Store for FakeStore {}
+
+#[tokio::test]
+async fn app_state_with_fake_store() -> Result<()> {
+    let store = Arc::new(FakeStore {
+        inner: MemoryStore::new(),
+        lookups: AtomicUsize::new(0),
+    });
+    let state = AppState::from_store(store.clone());
+
+    let user = User::new("John", "Doe", "j@d.c", "12345678").unwrap();
+    assert!(state.add_user(user).await.is_ok());
+
+    let login = LoginInfo::new("j@d.c", "12345678").unwrap();
+    let user_id = state.find_user(login).await.unwrap();
+    //One duplicate check on register, one on login
+    assert_eq!(store.lookups.load(Ordering::SeqCst), 2);
+
+    //Errors from the store reach the caller
+    assert!(state.add_session(user_id).await.is_err());
+    assert_eq!(state.print_session_count().await, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn memory_store() -> Result<()> {
+    let store = MemoryStore::new();
+    let user = User::new("John", "Doe", "j@d.c", "12345678").unwrap();
+
+    let stored = store.insert_user(&user, "hash").await.unwrap();
+    assert_eq!(stored.user_id(), 0);
+    assert_eq!(store.user_count().await.unwrap(), 1);
+    assert!(store.find_user_by_email("j@d.c").await.unwrap().is_some());
+    assert!(store.find_user_by_id(1).await.unwrap().is_none());
+    assert!(matches!(
+        store
+            .insert_user(
+                &User::new("Jane", "Doe", "J@D.C", "12345678").unwrap(),
+                "hash"
+            )
+            .await,
+        Err(AppError::Conflict(_))
+    ));
+
+    //Updating a user that was never stored fails
+    let ghost = StoredUser::from_parts(7, "Ghost", "User", "g@d.c", "hash");
+    assert!(store.update_user(&ghost).await.is_err());
+    ////////////////////////////////////////////////////////
+    let (session, _token) = Session::new(stored.user_id()).unwrap();
+    store.insert_session(&session).await.unwrap();
+    assert!(
+        store
+            .find_session(session.token_hash())
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    store.delete_session(session.token_hash()).await.unwrap();
+    assert_eq!(store.session_count().await.unwrap(), 0);
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use hyper::{Body, Request, StatusCode, Uri, header};
+use my_project::{
+    handlers::app_router,
+    structs::{
+        Pages,
+        app_state::AppState,
+        login::LoginInfo,
+        templates::{Flash, PageContext, Templates},
+        user::{User, UserProfile},
+    },
+};
+
+#[test]
+fn pages_render_with_layout() -> Result<()> {
+    let templates = Templates::from_dir(Pages::DIR);
+    templates.load_all(&Pages::ALL)?;
+
+    let context = PageContext::new()
+        .with_user(UserProfile::new("John", "Doe", "j@d.c").unwrap())
+        .with_flash(Flash::from_code(Flash::PROFILE_UPDATED));
+    let html = templates.render(Pages::PROFILE, &context)?;
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains(r#"value="John""#));
+    assert!(html.contains("Your profile has been saved."));
+    assert!(html.contains("function showFieldErrors"));
+
+    //Values are escaped
+    let context = PageContext::new().with_csrf_token(r#""><script>"#);
+    let html = templates.render(Pages::LOGIN, &context)?;
+    assert!(html.contains("&quot;&gt;&lt;script&gt;"));
+    assert!(!html.contains(r#""><script>"#));
+
+    assert!(
+        templates
+            .render("missing.html", &PageContext::new())
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn only_known_flash_codes() {
+    let uri: Uri = "/login?flash=logged_out".parse().unwrap();
+    let flash = Flash::from_uri(&uri).unwrap();
+    assert_eq!(flash.kind(), "info");
+    assert_eq!(flash.message(), "You have been logged out.");
+
+    let uri: Uri = "/login?flash=%3Cb%3Ehi".parse().unwrap();
+    assert_eq!(Flash::from_uri(&uri), None);
+    let uri: Uri = "/login".parse().unwrap();
+    assert_eq!(Flash::from_uri(&uri), None);
+}
+
+#[tokio::test]
+async fn home_greets_the_user() -> Result<()> {
+    let state = AppState::new_in_memory();
+    state
+        .add_user(User::new("John", "Doe", "j@d.c", "12345678").unwrap())
+        .await?;
+    let user_id = state
+        .find_user(LoginInfo::new("j@d.c", "12345678").unwrap())
+        .await?;
+    let token = state.add_session(user_id).await?;
+
+    let request = Request::builder()
+        .uri("/home")
+        .header(header::COOKIE, format!("session_id={}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app_router().dispatch(request, state.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/html; charset=utf-8"
+    );
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    assert!(String::from_utf8(body.to_vec())?.contains("Welcome, John"));
+
+    Ok(())
+}
@@ -5,6 +5,7 @@ use my_project::structs::user::{
 use serde_json::json;
 
 #[tokio::test]
+#[allow(clippy::useless_conversion)]
 async fn user_testing() -> Result<()> {
     ////////////////////////////////////////////////////////
     let user = User::new(
@@ -62,6 +63,7 @@ async fn user_testing() -> Result<()> {
 }
 
 #[tokio::test]
+#[allow(clippy::useless_conversion)]
 async fn stored_user() -> Result<()> {
     let user = User::new(
         "Joan".into(),
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use my_project::structs::{AppError, user::User};
+use serde_json::json;
+
+#[tokio::test]
+async fn validation_report() -> Result<()> {
+    let data = json!(
+    {
+        "first_name": "",
+        "last_name": "D",
+        "email": "john",
+        "password": "short"
+    });
+    let parsed: User = serde_json::from_value(data).unwrap();
+
+    let report = parsed.validation_report();
+    let codes: Vec<(&str, &str)> = report
+        .errors()
+        .iter()
+        .map(|err| (err.field, err.code))
+        .collect();
+    assert_eq!(
+        codes,
+        vec![
+            ("first_name", "required"),
+            ("last_name", "too_short"),
+            ("email", "invalid_format"),
+            ("password", "too_short"),
+        ]
+    );
+    assert!(matches!(parsed.validate(), Err(AppError::Validation(_))));
+    ////////////////////////////////////////////////////////
+    //Setters accept valid values and name the right field otherwise
+    let mut user = User::new("John", "Doe", "john@doe.com", "johnDoe123").unwrap();
+    assert!(user.set_email("new@doe.com".to_string()).is_ok());
+    assert_eq!(user.email(), "new@doe.com");
+    assert!(user.set_first_name("Johny".to_string()).is_ok());
+
+    let Err(AppError::Validation(report)) = user.set_last_name("D".to_string()) else {
+        panic!("short last name accepted");
+    };
+    assert!(report.has_field("last_name"));
+    assert_eq!(user.last_name(), "Doe");
+    assert!(user.set_password("1234".to_string()).is_err());
+
+    Ok(())
+}